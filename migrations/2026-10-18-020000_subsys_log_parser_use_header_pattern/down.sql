@@ -0,0 +1,2 @@
+alter table subsys_log_parser
+    drop column use_header_pattern;
@@ -0,0 +1,2 @@
+alter table subsys_log_parser
+    add column use_header_pattern tinyint(1) not null default 0; -- 是否使用记录头部的pattern代替log_split切分
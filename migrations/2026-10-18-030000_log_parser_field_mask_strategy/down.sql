@@ -0,0 +1,2 @@
+alter table log_parser_field
+    drop column mask_strategy;
@@ -0,0 +1,2 @@
+alter table log_parser_field
+    add column mask_strategy varchar(255) null; -- is_sensitive为1时的脱敏方式: redact(默认)/partial:N/hmac/drop
@@ -0,0 +1,243 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use crate::error::ParseError;
+use crate::header::LogHeader;
+use crate::value::Value;
+
+/// 解压后的内容默认最多 64MiB
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// snappy framing format 的开头
+const SNAPPY_STREAM_IDENTIFIER: &[u8] = b"\xff\x06\x00\x00sNaPpY";
+
+/// 头部 compress_algorithm 表示的压缩算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// `null`、`none` 或者没有 compress_algorithm
+    None,
+    Gzip,
+    Zlib,
+    Zstd,
+    /// lz4 frame 格式
+    Lz4,
+    /// snappy，支持 framing format 和不带 framing 的 raw 格式
+    Snappy,
+}
+
+impl Compression {
+    pub fn from_label(label: &str) -> Result<Self, ParseError> {
+        let compression = match label.trim().to_ascii_lowercase().as_str() {
+            "" | "null" | "none" => Compression::None,
+            "gzip" | "gz" => Compression::Gzip,
+            "zlib" => Compression::Zlib,
+            "zstd" | "zstandard" => Compression::Zstd,
+            "lz4" => Compression::Lz4,
+            "snappy" => Compression::Snappy,
+            _ => return Err(ParseError::UnknownCompression(label.to_string())),
+        };
+        Ok(compression)
+    }
+
+    pub fn from_header(log_header: &LogHeader) -> Result<Self, ParseError> {
+        match log_header
+            .attr
+            .get("compress_algorithm")
+            .and_then(Value::as_str)
+        {
+            Some(label) => Self::from_label(label),
+            None => Ok(Compression::None),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zlib => "zlib",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+            Compression::Snappy => "snappy",
+        }
+    }
+
+    /// 解压日志内容，解压后超过 `limit` 字节时返回错误，不压缩时原样借用
+    pub fn decompress<'a>(
+        &self,
+        content: &'a [u8],
+        limit: usize,
+    ) -> Result<Cow<'a, [u8]>, ParseError> {
+        let result = match self {
+            Compression::None => return Ok(Cow::Borrowed(content)),
+            Compression::Gzip => read_limited(flate2::read::MultiGzDecoder::new(content), limit),
+            Compression::Zlib => read_limited(flate2::read::ZlibDecoder::new(content), limit),
+            Compression::Zstd => zstd::stream::read::Decoder::new(content)
+                .and_then(|decoder| read_limited(decoder, limit)),
+            Compression::Lz4 => read_limited(lz4_flex::frame::FrameDecoder::new(content), limit),
+            Compression::Snappy if content.starts_with(SNAPPY_STREAM_IDENTIFIER) => {
+                read_limited(snap::read::FrameDecoder::new(content), limit)
+            }
+            Compression::Snappy => {
+                // raw 格式的开头记录了解压后的长度，先检查再解压
+                let len = snap::raw::decompress_len(content).map_err(|e| self.corrupt(e))?;
+                if len > limit {
+                    return Err(self.too_large(limit));
+                }
+                snap::raw::Decoder::new()
+                    .decompress_vec(content)
+                    .map_err(std::io::Error::other)
+            }
+        };
+        match result {
+            Ok(decompressed) if decompressed.len() > limit => Err(self.too_large(limit)),
+            Ok(decompressed) => Ok(Cow::Owned(decompressed)),
+            Err(error) => Err(self.corrupt(error)),
+        }
+    }
+
+    fn corrupt<E>(&self, error: E) -> ParseError
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ParseError::Decompression {
+            algorithm: self.name(),
+            source: error.into(),
+        }
+    }
+
+    fn too_large(&self, limit: usize) -> ParseError {
+        ParseError::DecompressedTooLarge {
+            algorithm: self.name(),
+            limit,
+        }
+    }
+}
+
+/// 最多读取 `limit + 1` 个字节，多读的一个字节用于判断是否超过限制
+fn read_limited<R: Read>(reader: R, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    reader
+        .take(limit.saturating_add(1) as u64)
+        .read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const ALL: [Compression; 5] = [
+        Compression::Gzip,
+        Compression::Zlib,
+        Compression::Zstd,
+        Compression::Lz4,
+        Compression::Snappy,
+    ];
+
+    fn compress(compression: Compression, content: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::None => content.to_vec(),
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zlib => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(content, 0).unwrap(),
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Snappy => {
+                let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+                encoder.write_all(content).unwrap();
+                encoder.into_inner().unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn parses_labels() {
+        assert_eq!(Compression::from_label("").unwrap(), Compression::None);
+        assert_eq!(Compression::from_label(" GZ ").unwrap(), Compression::Gzip);
+        assert_eq!(
+            Compression::from_label("zstandard").unwrap(),
+            Compression::Zstd
+        );
+        for compression in ALL {
+            assert_eq!(
+                Compression::from_label(compression.name()).unwrap(),
+                compression
+            );
+        }
+        assert!(matches!(
+            Compression::from_label("brotli"),
+            Err(ParseError::UnknownCompression(label)) if label == "brotli"
+        ));
+    }
+
+    #[test]
+    fn decompresses_every_algorithm() {
+        let content = "2024-01-01 INFO 中文日志\n".repeat(100).into_bytes();
+        for compression in ALL {
+            let compressed = compress(compression, &content);
+            let decompressed = compression.decompress(&compressed, content.len()).unwrap();
+            assert_eq!(decompressed.as_ref(), content.as_slice(), "{compression:?}");
+        }
+        let raw = snap::raw::Encoder::new().compress_vec(&content).unwrap();
+        let decompressed = Compression::Snappy.decompress(&raw, content.len()).unwrap();
+        assert_eq!(decompressed.as_ref(), content.as_slice());
+        assert!(matches!(
+            Compression::None.decompress(&content, 0).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_content_over_the_limit() {
+        let content = vec![b'a'; 1024 * 1024];
+        for compression in ALL {
+            let compressed = compress(compression, &content);
+            assert!(compressed.len() < content.len() / 10);
+            let error = compression
+                .decompress(&compressed, content.len() - 1)
+                .unwrap_err();
+            assert!(
+                matches!(error, ParseError::DecompressedTooLarge { limit, .. } if limit == content.len() - 1),
+                "{compression:?}: {error}"
+            );
+        }
+        let raw = snap::raw::Encoder::new().compress_vec(&content).unwrap();
+        assert!(matches!(
+            Compression::Snappy.decompress(&raw, 1024),
+            Err(ParseError::DecompressedTooLarge { limit: 1024, .. })
+        ));
+    }
+
+    #[test]
+    fn reports_corrupt_content() {
+        let corrupt = b"definitely not compressed";
+        for compression in ALL {
+            let error = compression.decompress(corrupt, 1024).unwrap_err();
+            assert!(
+                matches!(error, ParseError::Decompression { algorithm, .. } if algorithm == compression.name()),
+                "{compression:?}: {error}"
+            );
+        }
+        let mut truncated = compress(Compression::Gzip, b"some log line\n");
+        truncated.truncate(truncated.len() - 4);
+        assert!(matches!(
+            Compression::Gzip.decompress(&truncated, 1024),
+            Err(ParseError::Decompression { .. })
+        ));
+    }
+}
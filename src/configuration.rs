@@ -1 +1,267 @@
-struct Configuration {}
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::compress::DEFAULT_MAX_DECOMPRESSED_SIZE;
+use crate::datetime::Zone;
+use crate::dialect::SubsysSource;
+use crate::metadata::KeyMapping;
+use crate::subsys::SubsysChain;
+
+/// 应用配置，全部从环境变量(.env)中读取
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    pub database_url: String,
+    /// RULE_REFRESH_INTERVAL_SECS，规则缓存的刷新间隔
+    pub rule_refresh_interval: Duration,
+    /// STATS_INTERVAL_SECS，输出统计的间隔
+    pub stats_interval: Duration,
+    /// DATETIME_ZONE，不带时区的日期字段按哪个时区解释，local(默认)/UTC/+08:00
+    pub datetime_zone: Zone,
+    /// RULES_STRICT，为 true 时规则有错误或警告则拒绝启动或刷新，与 `validate --strict` 相同
+    pub rules_strict: bool,
+    /// MASK_HMAC_KEY，敏感字段 hmac 脱敏使用的密钥
+    pub mask_hmac_key: Option<Vec<u8>>,
+    /// REASSEMBLY_TIMEOUT_MS，跨日志块的事件等待后续日志块的最长时间，默认5000，为0时不重组
+    pub reassembly_timeout: Option<Duration>,
+    /// MAX_DECOMPRESSED_SIZE，压缩的日志块解压后的最大字节数，默认 64MiB
+    pub max_decompressed_size: usize,
+    pub key_mapping: KeyMapping,
+    /// HEADERLESS_SUBSYS_FROM，没有头部的记录按 key 或 topic 取子系统，不设置时这类记录无法解析
+    pub headerless_subsys_from: Option<SubsysSource>,
+    /// SUBSYS_CODE_SOURCES，`;` 分隔的子系统来源，默认 `fields0.SUBSYSCODE;subsyscode`，`regex:` 只能放在最后；
+    /// SUBSYS_CODE_FALLBACK，都没有时使用的子系统，不设置时这类记录无法解析
+    pub subsys_chain: SubsysChain,
+    pub consumer: ConsumerConfig,
+    pub output: OutputConfig,
+}
+
+impl Configuration {
+    pub fn from_env() -> anyhow::Result<Self> {
+        dotenvy::dotenv().ok();
+        Ok(Self {
+            database_url: env::var("DATABASE_URL").context("DATABASE_URL must be set")?,
+            rule_refresh_interval: Duration::from_secs(env_parse(
+                "RULE_REFRESH_INTERVAL_SECS",
+                60,
+            )?),
+            stats_interval: Duration::from_secs(env_parse("STATS_INTERVAL_SECS", 60)?),
+            datetime_zone: Zone::parse(&env::var("DATETIME_ZONE").unwrap_or_default())
+                .context("invalid DATETIME_ZONE")?,
+            rules_strict: env_parse("RULES_STRICT", false)?,
+            mask_hmac_key: env::var("MASK_HMAC_KEY").ok().map(String::into_bytes),
+            reassembly_timeout: Some(Duration::from_millis(env_parse(
+                "REASSEMBLY_TIMEOUT_MS",
+                5000,
+            )?))
+            .filter(|timeout| !timeout.is_zero()),
+            max_decompressed_size: env_parse(
+                "MAX_DECOMPRESSED_SIZE",
+                DEFAULT_MAX_DECOMPRESSED_SIZE,
+            )?,
+            key_mapping: KeyMapping::from_env()?,
+            headerless_subsys_from: env::var("HEADERLESS_SUBSYS_FROM")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.parse())
+                .transpose()
+                .context("invalid HEADERLESS_SUBSYS_FROM")?,
+            subsys_chain: SubsysChain::parse(
+                &env::var("SUBSYS_CODE_SOURCES").unwrap_or_default(),
+                env::var("SUBSYS_CODE_FALLBACK").ok().as_deref(),
+            )
+            .context("invalid SUBSYS_CODE_SOURCES")?,
+            consumer: ConsumerConfig::from_env()?,
+            output: OutputConfig::from_env()?,
+        })
+    }
+}
+
+/// Kafka 消费端配置
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    /// KAFKA_BROKERS，例如 `127.0.0.1:9092`
+    pub brokers: String,
+    /// KAFKA_GROUP_ID
+    pub group_id: String,
+    /// KAFKA_TOPICS，逗号分隔
+    pub topics: Vec<String>,
+    /// KAFKA_POLL_TIMEOUT_MS，单次poll的最长等待时间
+    pub poll_timeout: Duration,
+    /// KAFKA_AUTO_OFFSET_RESET，没有已提交offset时从哪里开始消费
+    pub auto_offset_reset: String,
+    /// KAFKA_CONSUMER_PROPERTIES，透传给 librdkafka 的其他配置，格式 `k1=v1,k2=v2`
+    pub properties: Vec<(String, String)>,
+}
+
+impl ConsumerConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let topics = split_list(&env::var("KAFKA_TOPICS").context("KAFKA_TOPICS must be set")?);
+        if topics.is_empty() {
+            anyhow::bail!("KAFKA_TOPICS must contain at least one topic");
+        }
+        Ok(Self {
+            brokers: env::var("KAFKA_BROKERS").context("KAFKA_BROKERS must be set")?,
+            group_id: env::var("KAFKA_GROUP_ID").unwrap_or_else(|_| "log-resolver".to_string()),
+            topics,
+            poll_timeout: Duration::from_millis(env_parse("KAFKA_POLL_TIMEOUT_MS", 1000)?),
+            auto_offset_reset: env::var("KAFKA_AUTO_OFFSET_RESET")
+                .unwrap_or_else(|_| "earliest".to_string()),
+            properties: split_properties(
+                &env::var("KAFKA_CONSUMER_PROPERTIES").unwrap_or_default(),
+            )?,
+        })
+    }
+}
+
+/// 解析结果的输出配置
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    /// OUTPUT_SINK，kafka(默认)/file/stdout
+    pub sink: SinkConfig,
+    /// OUTPUT_BATCH_SIZE，攒够多少条发送一次，每批送达后才提交已处理记录的 offset
+    pub batch_size: usize,
+    /// OUTPUT_RETRIES，投递失败的重试次数
+    pub retries: u32,
+    /// OUTPUT_RETRY_BACKOFF_MS，重试间隔，第n次重试等待n倍
+    pub retry_backoff: Duration,
+    /// DEAD_LETTER_TOPIC，解析失败和没有匹配的记录通过同一个输出端发往这个 topic，
+    /// 默认 log-resolver-dead-letter，为空时只记录日志
+    pub dead_letter_topic: Option<String>,
+    /// DEAD_LETTER_INCLUDE_RAW，死信中是否带上未脱敏的原始记录，默认 false，
+    /// 只有带原始记录的死信可以重放
+    pub dead_letter_include_raw: bool,
+    /// CONTINUITY_TOPIC，文件中缺失、重叠、重复和轮转的日志块事件发往这个 topic，
+    /// 默认 log-resolver-continuity，为空时只记录日志
+    pub continuity_topic: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SinkConfig {
+    Kafka(ProducerConfig),
+    /// OUTPUT_FILE_DIR，每个 topic 一个文件
+    File(PathBuf),
+    Stdout,
+}
+
+impl OutputConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let sink = match env::var("OUTPUT_SINK")
+            .unwrap_or_else(|_| "kafka".to_string())
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "kafka" => SinkConfig::Kafka(ProducerConfig::from_env()?),
+            "file" => SinkConfig::File(
+                env::var("OUTPUT_FILE_DIR")
+                    .context("OUTPUT_FILE_DIR must be set when OUTPUT_SINK=file")?
+                    .into(),
+            ),
+            "stdout" => SinkConfig::Stdout,
+            other => anyhow::bail!("unknown OUTPUT_SINK {other}, expected kafka/file/stdout"),
+        };
+        Ok(Self {
+            sink,
+            batch_size: env_parse("OUTPUT_BATCH_SIZE", 500)?,
+            retries: env_parse("OUTPUT_RETRIES", 3)?,
+            retry_backoff: Duration::from_millis(env_parse("OUTPUT_RETRY_BACKOFF_MS", 200)?),
+            dead_letter_topic: env_topic("DEAD_LETTER_TOPIC", "log-resolver-dead-letter"),
+            dead_letter_include_raw: env_parse("DEAD_LETTER_INCLUDE_RAW", false)?,
+            continuity_topic: env_topic("CONTINUITY_TOPIC", "log-resolver-continuity"),
+        })
+    }
+}
+
+/// Kafka 生产端配置
+#[derive(Debug, Clone)]
+pub struct ProducerConfig {
+    /// KAFKA_PRODUCER_BROKERS，默认与 KAFKA_BROKERS 相同
+    pub brokers: String,
+    /// KAFKA_PRODUCER_FLUSH_TIMEOUT_MS，等待投递回执的最长时间
+    pub flush_timeout: Duration,
+    /// KAFKA_PRODUCER_PROPERTIES，透传给 librdkafka 的其他配置，格式 `k1=v1,k2=v2`
+    pub properties: Vec<(String, String)>,
+}
+
+impl ProducerConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            brokers: env::var("KAFKA_PRODUCER_BROKERS")
+                .or_else(|_| env::var("KAFKA_BROKERS"))
+                .context("KAFKA_PRODUCER_BROKERS or KAFKA_BROKERS must be set")?,
+            flush_timeout: Duration::from_millis(env_parse(
+                "KAFKA_PRODUCER_FLUSH_TIMEOUT_MS",
+                30_000,
+            )?),
+            properties: split_properties(
+                &env::var("KAFKA_PRODUCER_PROPERTIES").unwrap_or_default(),
+            )?,
+        })
+    }
+}
+
+impl KeyMapping {
+    /// HEADER_NESTED_PREFIXES，逗号分隔，这些前缀的头部键拆分为嵌套对象，默认 fields0，为空时不嵌套；
+    /// HEADER_KEY_CASE，嵌套后键名的大小写，lower(默认)/preserve；
+    /// HEADER_KUBERNETES_KEYS，`pod=fields0.k8s_pod,namespace=...` 形式，设置后替换默认的映射
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut key_mapping = KeyMapping::default();
+        if let Ok(prefixes) = env::var("HEADER_NESTED_PREFIXES") {
+            key_mapping.nested_prefixes = split_list(&prefixes);
+        }
+        if let Ok(key_case) = env::var("HEADER_KEY_CASE") {
+            key_mapping.key_case = key_case.parse().context("invalid HEADER_KEY_CASE")?;
+        }
+        if let Ok(keys) = env::var("HEADER_KUBERNETES_KEYS") {
+            key_mapping.kubernetes = split_properties(&keys)?
+                .into_iter()
+                .map(|(field, key)| Ok((key, field.parse()?)))
+                .collect::<anyhow::Result<_>>()
+                .context("invalid HEADER_KUBERNETES_KEYS")?;
+        }
+        Ok(key_mapping)
+    }
+}
+
+/// 逗号分隔的列表，忽略空项
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `k1=v1,k2=v2` 形式的配置项
+fn split_properties(s: &str) -> anyhow::Result<Vec<(String, String)>> {
+    split_list(s)
+        .into_iter()
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) => Ok((k.trim().to_string(), v.trim().to_string())),
+            None => anyhow::bail!("invalid property {kv}, expected key=value"),
+        })
+        .collect()
+}
+
+/// 未设置时使用默认值，设置为空时为 None
+fn env_topic(key: &str, default: &str) -> Option<String> {
+    Some(env::var(key).unwrap_or_else(|_| default.to_string()))
+        .map(|topic| topic.trim().to_string())
+        .filter(|topic| !topic.is_empty())
+}
+
+fn env_parse<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(key) {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid value for {key}: {v}")),
+        Err(_) => Ok(default),
+    }
+}
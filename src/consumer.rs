@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::message::Message;
+use rdkafka::{Offset, TopicPartitionList};
+
+use crate::configuration::ConsumerConfig;
+
+/// 从 Kafka 拉取到的一条原始记录
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub key: String,
+    pub value: Vec<u8>,
+    /// 消息时间戳(毫秒)，没有时为0
+    pub timestamp: u64,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// 每个分区下一条要消费的 offset，即已处理的最后一条记录的 offset + 1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionOffsets(HashMap<(String, i32), i64>);
+
+impl PartitionOffsets {
+    /// 记录已经处理完，同一分区只保留最大的 offset
+    pub fn handled(&mut self, record: &Record) {
+        let next = self
+            .0
+            .entry((record.topic.clone(), record.partition))
+            .or_insert(record.offset + 1);
+        *next = (*next).max(record.offset + 1);
+    }
+
+    /// 记录中还有未处理完的数据，同一分区只保留最小的 offset，下次从这条记录开始消费
+    pub fn pending(&mut self, topic: &str, partition: i32, offset: i64) {
+        let next = self
+            .0
+            .entry((topic.to_string(), partition))
+            .or_insert(offset);
+        *next = (*next).min(offset);
+    }
+
+    /// 每个分区的 offset 不超过 `limits` 中同一分区的 offset
+    pub fn capped(&self, limits: &PartitionOffsets) -> PartitionOffsets {
+        let mut offsets = self.clone();
+        for (key, limit) in &limits.0 {
+            if let Some(offset) = offsets.0.get_mut(key) {
+                *offset = (*offset).min(*limit);
+            }
+        }
+        offsets
+    }
+
+    pub fn get(&self, topic: &str, partition: i32) -> Option<i64> {
+        self.0.get(&(topic.to_string(), partition)).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn to_list(&self) -> anyhow::Result<TopicPartitionList> {
+        let mut tpl = TopicPartitionList::with_capacity(self.0.len());
+        for ((topic, partition), offset) in &self.0 {
+            tpl.add_partition_offset(topic, *partition, Offset::Offset(*offset))?;
+        }
+        Ok(tpl)
+    }
+}
+
+/// Kafka 消费者
+///
+/// 关闭了自动提交，只提交 handler 确认已经处理完的 offset
+pub struct KafkaRecordSource {
+    consumer: BaseConsumer,
+    config: ConsumerConfig,
+}
+
+impl KafkaRecordSource {
+    pub fn new(config: &ConsumerConfig) -> anyhow::Result<Self> {
+        let mut client_config = ClientConfig::new();
+        for (key, value) in &config.properties {
+            client_config.set(key, value);
+        }
+        let consumer: BaseConsumer = client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", &config.auto_offset_reset)
+            .create()
+            .context("failed to create kafka consumer")?;
+        let topics: Vec<&str> = config.topics.iter().map(String::as_str).collect();
+        consumer
+            .subscribe(&topics)
+            .with_context(|| format!("failed to subscribe to {topics:?}"))?;
+        log::info!("subscribed to {:?} as {}", topics, config.group_id);
+        Ok(Self {
+            consumer,
+            config: config.clone(),
+        })
+    }
+
+    /// 拉取一条记录，超时则返回 None
+    ///
+    /// librdkafka 会自行重试临时的错误，这类错误只记录日志并返回 None，
+    /// 只有客户端进入致命错误状态时才返回错误
+    pub fn poll(&self) -> anyhow::Result<Option<Record>> {
+        match self.consumer.poll(self.config.poll_timeout) {
+            None => Ok(None),
+            Some(Err(error)) => {
+                if let Some((code, reason)) = self.consumer.client().fatal_error() {
+                    anyhow::bail!("fatal kafka error {code:?}: {reason}");
+                }
+                log::warn!("error while polling kafka: {error}");
+                Ok(None)
+            }
+            Some(Ok(message)) => Ok(Some(Record {
+                key: message
+                    .key()
+                    .map(|k| String::from_utf8_lossy(k).into_owned())
+                    .unwrap_or_default(),
+                value: message.payload().map(<[u8]>::to_vec).unwrap_or_default(),
+                timestamp: message
+                    .timestamp()
+                    .to_millis()
+                    .map(|t| t.max(0) as u64)
+                    .unwrap_or(0),
+                topic: message.topic().to_string(),
+                partition: message.partition(),
+                offset: message.offset(),
+            })),
+        }
+    }
+
+    /// 异步提交各分区的 offset
+    pub fn commit_offsets(&self, offsets: &PartitionOffsets) -> anyhow::Result<()> {
+        self.consumer
+            .commit(&offsets.to_list()?, CommitMode::Async)
+            .context("failed to commit offsets")
+    }
+
+    /// 持续消费直到 `shutdown` 被置为 true，poll 超时没有拉到记录时以 None 调用 handler，
+    /// 用于处理定时的任务，`shutdown` 被置为 true 后还会以 None 再调用一次
+    ///
+    /// handler 返回错误时停止消费并返回该错误；
+    /// 由 handler 决定何时提交: 返回的 offset 与上次提交的不同时异步提交，
+    /// 退出消费组前同步提交最后一次返回的 offset
+    pub fn run_with_idle<F>(&self, shutdown: &AtomicBool, mut handler: F) -> anyhow::Result<()>
+    where
+        F: FnMut(Option<&Record>) -> anyhow::Result<Option<PartitionOffsets>>,
+    {
+        let mut committed = PartitionOffsets::default();
+        let result = self.consume(shutdown, &mut handler, &mut committed);
+        log::info!("shutting down kafka consumer");
+        self.close(&committed)?;
+        result
+    }
+
+    fn consume<F>(
+        &self,
+        shutdown: &AtomicBool,
+        handler: &mut F,
+        committed: &mut PartitionOffsets,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(Option<&Record>) -> anyhow::Result<Option<PartitionOffsets>>,
+    {
+        while !shutdown.load(Ordering::Relaxed) {
+            let record = self.poll()?;
+            if let Some(offsets) = handler(record.as_ref())? {
+                self.commit_changed(offsets, committed)?;
+            }
+        }
+        if let Some(offsets) = handler(None)? {
+            self.commit_changed(offsets, committed)?;
+        }
+        Ok(())
+    }
+
+    fn commit_changed(
+        &self,
+        offsets: PartitionOffsets,
+        committed: &mut PartitionOffsets,
+    ) -> anyhow::Result<()> {
+        if offsets.is_empty() || offsets == *committed {
+            return Ok(());
+        }
+        self.commit_offsets(&offsets)?;
+        *committed = offsets;
+        Ok(())
+    }
+
+    /// 同步提交已经处理过的 offset 并退出消费组
+    pub fn close(&self, handled: &PartitionOffsets) -> anyhow::Result<()> {
+        if !handled.is_empty() {
+            self.consumer
+                .commit(&handled.to_list()?, CommitMode::Sync)
+                .context("failed to commit handled offsets")?;
+        }
+        self.consumer.unsubscribe();
+        Ok(())
+    }
+}
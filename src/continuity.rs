@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::consumer::Record;
+use crate::error::{HeaderError, ParseError};
+use crate::header::LogHeader;
+use crate::sink::OutputMessage;
+
+/// 日志块与同一文件中已收到内容的关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Continuity {
+    /// 与上一个日志块之间缺少了数据
+    Gap,
+    /// 开头的一部分已经收到过
+    Overlap,
+    /// 整个日志块都已经收到过，例如采集端重发，丢弃
+    Duplicate,
+    /// file_offset 回到 0，文件被轮转或截断
+    Rotation,
+}
+
+/// 不连续的日志块，作为审计事件输出
+#[derive(Debug, Clone, Serialize)]
+pub struct ContinuityEvent {
+    pub kind: Continuity,
+    pub hostname: String,
+    pub path: String,
+    pub subsys_code: String,
+    /// 期望的 file_offset，即已收到内容的末尾
+    pub expected_offset: u64,
+    pub file_offset: u64,
+    pub data_length: u64,
+    pub block_index: Option<u64>,
+    /// 上一个日志块的 block_index
+    pub previous_block_index: Option<u64>,
+    /// gap 时为缺少的字节数，overlap/duplicate 时为重复的字节数
+    pub bytes: u64,
+    /// 日志块所在的 topic/partition/offset
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub detected_at: DateTime<Local>,
+}
+
+impl ContinuityEvent {
+    pub fn to_message(&self, topic: &str) -> anyhow::Result<OutputMessage> {
+        Ok(OutputMessage {
+            topic: topic.to_string(),
+            key: Some(format!("{}:{}", self.hostname, self.path)),
+            payload: serde_json::to_vec(self)?,
+        })
+    }
+}
+
+/// 累计的统计，定期输出到日志
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContinuityStats {
+    /// 带有 file_offset 和 data_length 的日志块数
+    pub blocks: u64,
+    pub gaps: u64,
+    pub missing_bytes: u64,
+    pub overlaps: u64,
+    pub duplicates: u64,
+    pub duplicate_bytes: u64,
+    pub rotations: u64,
+}
+
+#[derive(Debug)]
+struct FileState {
+    /// 已收到内容的末尾
+    next_offset: u64,
+    block_index: Option<u64>,
+}
+
+/// 按 (hostname, path) 跟踪每个文件已收到的范围
+///
+/// 只在进程内存中保存，重启后每个文件的第一个日志块作为新的起点
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    files: HashMap<(String, String), FileState>,
+    stats: ContinuityStats,
+}
+
+impl OffsetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> ContinuityStats {
+        self.stats
+    }
+
+    /// 记录一个日志块，不连续时返回事件，`header` 是 `record` 已经解析的头部
+    ///
+    /// 没有 path、file_offset、data_length 时不跟踪，
+    /// file_offset + data_length 溢出时返回错误，也不跟踪
+    pub fn observe(
+        &mut self,
+        header: &LogHeader,
+        record: &Record,
+    ) -> Result<Option<ContinuityEvent>, ParseError> {
+        let (Some(key), Some(file_offset), Some(data_length)) =
+            (header.source_file(), header.file_offset, header.data_length)
+        else {
+            return Ok(None);
+        };
+        let block_index = header.block_index;
+        let Some(end) = file_offset.checked_add(data_length) else {
+            return Err(ParseError::Header(HeaderError::InvalidValue {
+                key: "data_length".to_string(),
+                value: data_length.to_string(),
+                reason: format!("file_offset {file_offset} + data_length overflows"),
+            }));
+        };
+        self.stats.blocks += 1;
+
+        let Some(state) = self.files.get_mut(&key) else {
+            self.files.insert(
+                key,
+                FileState {
+                    next_offset: end,
+                    block_index,
+                },
+            );
+            return Ok(None);
+        };
+        let expected = state.next_offset;
+        let previous_block_index = state.block_index;
+        let (kind, bytes) = if file_offset == expected {
+            state.next_offset = end;
+            state.block_index = block_index;
+            return Ok(None);
+        } else if file_offset > expected {
+            self.stats.gaps += 1;
+            self.stats.missing_bytes += file_offset - expected;
+            (Continuity::Gap, file_offset - expected)
+        } else if file_offset == 0 {
+            self.stats.rotations += 1;
+            (Continuity::Rotation, 0)
+        } else if end <= expected {
+            self.stats.duplicates += 1;
+            self.stats.duplicate_bytes += data_length;
+            (Continuity::Duplicate, data_length)
+        } else {
+            self.stats.overlaps += 1;
+            (Continuity::Overlap, expected - file_offset)
+        };
+        // 重复的日志块不改变已收到的范围
+        if kind != Continuity::Duplicate {
+            state.next_offset = end;
+            state.block_index = block_index;
+        }
+        let (hostname, path) = key;
+        Ok(Some(ContinuityEvent {
+            kind,
+            hostname,
+            path,
+            subsys_code: header.subsys_code.clone(),
+            expected_offset: expected,
+            file_offset,
+            data_length,
+            block_index,
+            previous_block_index,
+            bytes,
+            topic: record.topic.clone(),
+            partition: record.partition,
+            offset: record.offset,
+            detected_at: Local::now(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header::encode_header;
+
+    use super::*;
+
+    fn header(path: &str, file_offset: u64, data_length: u64) -> LogHeader {
+        let header = encode_header([
+            ("subsyscode", "APP"),
+            ("hostname", "host"),
+            ("path", path),
+            ("file_offset", &file_offset.to_string()),
+            ("data_length", &data_length.to_string()),
+        ]);
+        LogHeader::from_bytes(header.as_bytes()).unwrap()
+    }
+
+    fn record(offset: i64) -> Record {
+        Record {
+            key: "key".to_string(),
+            value: Vec::new(),
+            timestamp: 0,
+            topic: "raw".to_string(),
+            partition: 1,
+            offset,
+        }
+    }
+
+    fn observe(
+        tracker: &mut OffsetTracker,
+        file_offset: u64,
+        data_length: u64,
+    ) -> Option<Continuity> {
+        let header = header("/a.log", file_offset, data_length);
+        let event = tracker.observe(&header, &record(0)).unwrap();
+        event.map(|event| event.kind)
+    }
+
+    #[test]
+    fn adjacent_blocks_are_continuous() {
+        let mut tracker = OffsetTracker::new();
+        assert_eq!(observe(&mut tracker, 100, 10), None);
+        assert_eq!(observe(&mut tracker, 110, 10), None);
+        // 不同文件分别跟踪
+        let other = header("/b.log", 500, 10);
+        assert!(tracker.observe(&other, &record(0)).unwrap().is_none());
+        assert_eq!(tracker.stats().blocks, 3);
+    }
+
+    #[test]
+    fn reports_gap() {
+        let mut tracker = OffsetTracker::new();
+        observe(&mut tracker, 0, 10);
+        let event = tracker
+            .observe(&header("/a.log", 15, 10), &record(7))
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind, Continuity::Gap);
+        assert_eq!((event.expected_offset, event.bytes), (10, 5));
+        assert_eq!(
+            (event.hostname.as_str(), event.path.as_str()),
+            ("host", "/a.log")
+        );
+        assert_eq!(
+            (event.topic.as_str(), event.partition, event.offset),
+            ("raw", 1, 7)
+        );
+        assert_eq!(event.subsys_code, "APP");
+        assert_eq!(observe(&mut tracker, 25, 10), None);
+        assert_eq!(tracker.stats().missing_bytes, 5);
+    }
+
+    #[test]
+    fn reports_overlap_and_duplicate() {
+        let mut tracker = OffsetTracker::new();
+        observe(&mut tracker, 0, 10);
+        assert_eq!(observe(&mut tracker, 2, 8), Some(Continuity::Duplicate));
+        // 重复的日志块不改变已收到的范围
+        assert_eq!(observe(&mut tracker, 10, 10), None);
+        assert_eq!(observe(&mut tracker, 15, 10), Some(Continuity::Overlap));
+        assert_eq!(observe(&mut tracker, 25, 10), None);
+        let stats = tracker.stats();
+        assert_eq!((stats.duplicates, stats.duplicate_bytes), (1, 8));
+        assert_eq!(stats.overlaps, 1);
+    }
+
+    #[test]
+    fn reports_rotation() {
+        let mut tracker = OffsetTracker::new();
+        observe(&mut tracker, 0, 10);
+        observe(&mut tracker, 10, 10);
+        assert_eq!(observe(&mut tracker, 0, 5), Some(Continuity::Rotation));
+        assert_eq!(observe(&mut tracker, 5, 5), None);
+        assert_eq!(tracker.stats().rotations, 1);
+    }
+
+    #[test]
+    fn overflowing_end_offset_is_a_header_error() {
+        let mut tracker = OffsetTracker::new();
+        let error = tracker
+            .observe(&header("/a.log", u64::MAX, 1), &record(0))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::Header(HeaderError::InvalidValue { ref key, .. }) if key == "data_length"
+        ));
+        assert_eq!(tracker.stats().blocks, 0);
+
+        let mut header = header("/a.log", 0, 10);
+        header.path = None;
+        assert!(tracker.observe(&header, &record(0)).unwrap().is_none());
+    }
+}
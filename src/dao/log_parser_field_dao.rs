@@ -8,13 +8,13 @@ pub fn query_by_log_parser_rule_id_and_name_in_capture<T>(
     conn: &mut diesel::MysqlConnection,
     id: T,
     name_in_capture: &str,
-) -> Option<LogParserField>
+) -> diesel::QueryResult<Option<LogParserField>>
 where
     T: Into<u64>,
 {
     let idu64 = id.into();
     log::debug!("id: {:?} name_in_capture: {name_in_capture}", idu64);
-    let log_parser_field = schema::log_parser_field::dsl::log_parser_field
+    schema::log_parser_field::dsl::log_parser_field
         .filter(
             schema::log_parser_field::log_parser_rule_id
                 .eq(idu64)
@@ -22,13 +22,13 @@ where
         )
         .select(LogParserField::as_select())
         .first(conn)
-        .optional();
+        .optional()
+}
 
-    match log_parser_field {
-        Ok(l) => l,
-        Err(error) => {
-            log::error!("Error loading log_parser_rule: {:?}", error);
-            None
-        }
-    }
+/// 查询所有记录，按 id 排序，出错时返回错误而不是空结果
+pub fn query_all(conn: &mut diesel::MysqlConnection) -> diesel::QueryResult<Vec<LogParserField>> {
+    crate::schema::log_parser_field::dsl::log_parser_field
+        .order(schema::log_parser_field::id.asc())
+        .select(LogParserField::as_select())
+        .get_results(conn)
 }
@@ -6,22 +6,23 @@ use crate::schema;
 pub fn query_by_log_parser_rule_id<T>(
     conn: &mut diesel::MysqlConnection,
     id: T,
-) -> Vec<LogParserPattern>
+) -> diesel::QueryResult<Vec<LogParserPattern>>
 where
     T: Into<u64>,
 {
     let idu64 = id.into();
     log::debug!("id: {:?}", idu64);
-    let log_parser_pattern = crate::schema::log_parser_pattern::dsl::log_parser_pattern
+    crate::schema::log_parser_pattern::dsl::log_parser_pattern
         .filter(schema::log_parser_pattern::log_parser_rule_id.eq(idu64))
+        .order(schema::log_parser_pattern::id.asc())
         .select(LogParserPattern::as_select())
-        .get_results(conn);
+        .get_results(conn)
+}
 
-    match log_parser_pattern {
-        Ok(l) => l,
-        Err(error) => {
-            log::error!("Error loading log_parser_rule: {:?}", error);
-            Vec::new()
-        }
-    }
+/// 查询所有记录，按 id 排序，出错时返回错误而不是空结果
+pub fn query_all(conn: &mut diesel::MysqlConnection) -> diesel::QueryResult<Vec<LogParserPattern>> {
+    crate::schema::log_parser_pattern::dsl::log_parser_pattern
+        .order(schema::log_parser_pattern::id.asc())
+        .select(LogParserPattern::as_select())
+        .get_results(conn)
 }
@@ -3,23 +3,26 @@ use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, Select
 use crate::models::*;
 use crate::schema;
 
-pub fn query_by_id<T>(conn: &mut diesel::MysqlConnection, id: T) -> Option<LogParserRule>
+pub fn query_by_id<T>(
+    conn: &mut diesel::MysqlConnection,
+    id: T,
+) -> diesel::QueryResult<Option<LogParserRule>>
 where
     T: Into<u64>,
 {
     let idu64 = id.into();
     log::debug!("query_by_id: {:?}", idu64);
-    let log_parser_rule = crate::schema::log_parser_rule::dsl::log_parser_rule
+    crate::schema::log_parser_rule::dsl::log_parser_rule
         .filter(schema::log_parser_rule::id.eq(idu64))
         .select(LogParserRule::as_select())
         .first(conn)
-        .optional();
+        .optional()
+}
 
-    match log_parser_rule {
-        Ok(post) => post,
-        Err(error) => {
-            log::error!("Error loading log_parser_rule: {:?}", error);
-            None
-        }
-    }
+/// 查询所有记录，按 id 排序，出错时返回错误而不是空结果
+pub fn query_all(conn: &mut diesel::MysqlConnection) -> diesel::QueryResult<Vec<LogParserRule>> {
+    crate::schema::log_parser_rule::dsl::log_parser_rule
+        .order(schema::log_parser_rule::id.asc())
+        .select(LogParserRule::as_select())
+        .get_results(conn)
 }
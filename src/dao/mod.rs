@@ -1,5 +1,5 @@
 pub mod log_parser_field_dao;
+pub mod log_parser_pattern_dao;
 pub mod log_parser_rule_dao;
 pub mod subsys_log_parser_config_dao;
 pub mod sys_subsys_config_dao;
-pub mod log_parser_pattern_dao;
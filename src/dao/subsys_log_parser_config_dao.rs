@@ -1,4 +1,4 @@
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
 
 use crate::models::*;
 use crate::schema;
@@ -6,19 +6,21 @@ use crate::schema;
 pub fn query_by_subsys_code(
     conn: &mut diesel::MysqlConnection,
     subsys_code: &str,
-) -> Vec<SubsysLogParser> {
+) -> diesel::QueryResult<Vec<SubsysLogParser>> {
     log::debug!("query_by_subsys_code: {}", subsys_code);
-    let subsys_log_parser = crate::schema::subsys_log_parser::dsl::subsys_log_parser
+    crate::schema::subsys_log_parser::dsl::subsys_log_parser
         .filter(schema::subsys_log_parser::subsys_code.eq(subsys_code))
         .filter(schema::subsys_log_parser::status.eq(true))
+        .order(schema::subsys_log_parser::id.asc())
         .select(SubsysLogParser::as_select())
-        .get_results(conn); // This allows for returning an Option<Post>, otherwise it will throw an error
+        .get_results(conn)
+}
 
-    match subsys_log_parser {
-        Ok(post) => post,
-        Err(error) => {
-            log::error!("Error loading post: {:?}", error);
-            Vec::new()
-        }
-    }
+/// 查询所有启用的记录，按 id 排序，出错时返回错误而不是空结果
+pub fn query_all(conn: &mut diesel::MysqlConnection) -> diesel::QueryResult<Vec<SubsysLogParser>> {
+    crate::schema::subsys_log_parser::dsl::subsys_log_parser
+        .filter(schema::subsys_log_parser::status.eq(true))
+        .order(schema::subsys_log_parser::id.asc())
+        .select(SubsysLogParser::as_select())
+        .get_results(conn)
 }
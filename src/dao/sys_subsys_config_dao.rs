@@ -6,19 +6,19 @@ use crate::schema;
 pub fn query_by_subsys_code(
     conn: &mut diesel::MysqlConnection,
     subsys_code: &str,
-) -> Option<SysSubsysConfig> {
+) -> diesel::QueryResult<Option<SysSubsysConfig>> {
     log::debug!("query_by_subsys_code: {}", subsys_code);
-    let sys_subsys_config = crate::schema::sys_subsys_config::dsl::sys_subsys_config
+    crate::schema::sys_subsys_config::dsl::sys_subsys_config
         .filter(schema::sys_subsys_config::subsys_code.eq(subsys_code))
         .select(SysSubsysConfig::as_select())
         .first(conn)
-        .optional(); // This allows for returning an Option<Post>, otherwise it will throw an error
+        .optional() // This allows for returning an Option<Post>, otherwise it will throw an error
+}
 
-    match sys_subsys_config {
-        Ok(post) => post,
-        Err(error) => {
-            log::error!("Error loading post: {:?}", error);
-            None
-        }
-    }
+/// 查询所有记录，按 id 排序，出错时返回错误而不是空结果
+pub fn query_all(conn: &mut diesel::MysqlConnection) -> diesel::QueryResult<Vec<SysSubsysConfig>> {
+    crate::schema::sys_subsys_config::dsl::sys_subsys_config
+        .order(schema::sys_subsys_config::id.asc())
+        .select(SysSubsysConfig::as_select())
+        .get_results(conn)
 }
@@ -0,0 +1,414 @@
+use anyhow::{Context, anyhow};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+
+/// 日期字段(type=10)的格式，由 log_parser_field.format_pattern 解析得到
+///
+/// format_pattern 支持：
+/// - Java `SimpleDateFormat`/`DateTimeFormatter` 风格，例如 `yyyy-MM-dd HH:mm:ss.SSS`
+/// - 含有 `%` 时按 strftime 处理，例如 `%Y-%m-%d %H:%M:%S%.f`
+/// - `iso8601`/`ISO_LOCAL_DATE_TIME`/`ISO_OFFSET_DATE_TIME`
+/// - `epoch_s`/`epoch_ms`/`epoch_us`/`epoch_ns`，Unix 时间戳，秒可以带最多9位小数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// strftime 格式，`has_offset` 表示格式中包含时区
+    Pattern {
+        strftime: String,
+        has_offset: bool,
+    },
+    Iso8601,
+    EpochSeconds,
+    EpochMillis,
+    EpochMicros,
+    EpochNanos,
+}
+
+impl DateFormat {
+    pub fn from_format_pattern(format_pattern: &str) -> anyhow::Result<Self> {
+        let format_pattern = format_pattern.trim();
+        let format = match format_pattern.to_ascii_lowercase().as_str() {
+            "" => return Err(anyhow!("empty date format")),
+            "iso8601"
+            | "iso"
+            | "iso_local_date_time"
+            | "iso_offset_date_time"
+            | "iso_date_time" => DateFormat::Iso8601,
+            "epoch" | "epoch_s" | "epoch_second" | "epoch_seconds" | "unix" => {
+                DateFormat::EpochSeconds
+            }
+            "epoch_ms" | "epoch_milli" | "epoch_millis" | "unix_ms" => DateFormat::EpochMillis,
+            "epoch_us" | "epoch_micro" | "epoch_micros" | "unix_us" => DateFormat::EpochMicros,
+            "epoch_ns" | "epoch_nano" | "epoch_nanos" | "unix_ns" => DateFormat::EpochNanos,
+            _ => {
+                let strftime = if format_pattern.contains('%') {
+                    format_pattern.to_string()
+                } else {
+                    java_to_strftime(format_pattern)?
+                };
+                let has_offset = ["%z", "%:z", "%::z", "%#z"]
+                    .iter()
+                    .any(|z| strftime.contains(z));
+                DateFormat::Pattern {
+                    strftime,
+                    has_offset,
+                }
+            }
+        };
+        Ok(format)
+    }
+
+    /// 解析时间，格式中不含时区时按 `zone` 处理
+    pub fn parse(&self, value: &str, zone: &Zone) -> anyhow::Result<DateTime<FixedOffset>> {
+        let value = value.trim();
+        match self {
+            DateFormat::Pattern {
+                strftime,
+                has_offset: true,
+            } => DateTime::parse_from_str(value, strftime)
+                .with_context(|| format!("{value:?} does not match {strftime:?}")),
+            DateFormat::Pattern { strftime, .. } => {
+                let naive = NaiveDateTime::parse_from_str(value, strftime)
+                    .or_else(|_| {
+                        // 只有日期没有时间
+                        NaiveDate::parse_from_str(value, strftime)
+                            .map(|d| d.and_time(NaiveTime::MIN))
+                    })
+                    .with_context(|| format!("{value:?} does not match {strftime:?}"))?;
+                zone.resolve(&naive)
+            }
+            DateFormat::Iso8601 => {
+                if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+                    return Ok(dt);
+                }
+                let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                    .iter()
+                    .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+                    .with_context(|| format!("{value:?} is not an ISO 8601 date time"))?;
+                zone.resolve(&naive)
+            }
+            DateFormat::EpochSeconds => epoch_nanos(value, parse_epoch_seconds(value)?),
+            DateFormat::EpochMillis => epoch_nanos(value, parse_i64(value)?.checked_mul(1_000_000)),
+            DateFormat::EpochMicros => epoch_nanos(value, parse_i64(value)?.checked_mul(1_000)),
+            DateFormat::EpochNanos => epoch_nanos(value, Some(parse_i64(value)?)),
+        }
+    }
+}
+
+fn parse_i64(value: &str) -> anyhow::Result<i64> {
+    value
+        .parse()
+        .with_context(|| format!("{value:?} is not an integer timestamp"))
+}
+
+/// 整数和小数部分分开解析，避免经过 f64 丢失精度，超出 i64 纳秒范围时返回 None
+fn parse_epoch_seconds(value: &str) -> anyhow::Result<Option<i64>> {
+    let invalid = || anyhow!("{value:?} is not an epoch second");
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    if fraction.len() > 9 {
+        return Err(anyhow!("{value:?} has more than 9 fractional digits"));
+    }
+    let Ok(seconds) = format!("0{integer}").parse::<i64>() else {
+        return Ok(None);
+    };
+    let nanos: i64 = format!("{fraction:0<9}").parse().map_err(|_| invalid())?;
+    let total = seconds
+        .checked_mul(1_000_000_000)
+        .and_then(|s| s.checked_add(nanos));
+    Ok(if negative { total.map(|t| -t) } else { total })
+}
+
+fn epoch_nanos(value: &str, nanos: Option<i64>) -> anyhow::Result<DateTime<FixedOffset>> {
+    let nanos = nanos.ok_or_else(|| anyhow!("timestamp {value:?} is out of range"))?;
+    Ok(DateTime::<Utc>::from_timestamp_nanos(nanos).fixed_offset())
+}
+
+/// 不带时区的时间按哪个时区解释
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Zone {
+    /// 进程所在时区
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// `local`、`UTC`/`Z` 或 `+08:00`/`-0530` 形式的偏移
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("local") || s.is_empty() {
+            return Ok(Zone::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") || s.eq_ignore_ascii_case("z") {
+            return Ok(Zone::Fixed(Utc.fix()));
+        }
+        let offset = s
+            .strip_prefix("UTC")
+            .or_else(|| s.strip_prefix("GMT"))
+            .unwrap_or(s);
+        let offset: FixedOffset = offset
+            .parse()
+            .map_err(|_| anyhow!("invalid time zone {s:?}, expected local, UTC or +08:00"))?;
+        Ok(Zone::Fixed(offset))
+    }
+
+    pub fn resolve(&self, naive: &NaiveDateTime) -> anyhow::Result<DateTime<FixedOffset>> {
+        match self {
+            Zone::Fixed(offset) => offset
+                .from_local_datetime(naive)
+                .single()
+                .ok_or_else(|| anyhow!("{naive} is ambiguous in {offset}")),
+            // 夏令时切换时有两个对应时刻，取较早的一个
+            Zone::Local => Local
+                .from_local_datetime(naive)
+                .earliest()
+                .map(|dt| dt.fixed_offset())
+                .ok_or_else(|| anyhow!("{naive} does not exist in local time zone")),
+        }
+    }
+}
+
+/// 把 Java 的日期格式转换成 strftime
+///
+/// `hh` 在没有上下午标记 `a` 时按24小时制处理，这是最常见的误用
+pub fn java_to_strftime(pattern: &str) -> anyhow::Result<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let twelve_hour = has_am_pm_marker(&chars);
+    let mut out = String::with_capacity(pattern.len() * 2);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // 引号中的内容原样输出，两个连续的引号表示一个引号
+        if c == '\'' {
+            if chars.get(i + 1) == Some(&'\'') {
+                out.push('\'');
+                i += 2;
+                continue;
+            }
+            i += 1;
+            loop {
+                match (chars.get(i), chars.get(i + 1)) {
+                    (None, _) => {
+                        return Err(anyhow!("unterminated quote in date format {pattern:?}"));
+                    }
+                    (Some('\''), Some('\'')) => {
+                        out.push('\'');
+                        i += 2;
+                    }
+                    (Some('\''), _) => break,
+                    (Some(&c), _) => {
+                        push_literal(&mut out, c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            // `.SSS` 之类的小数秒带着点一起转换，允许任意位数
+            if c == '.' && chars.get(i + 1) == Some(&'S') {
+                out.push_str("%.f");
+                i += 1 + run_length(&chars, i + 1);
+                continue;
+            }
+            push_literal(&mut out, c);
+            i += 1;
+            continue;
+        }
+        let n = run_length(&chars, i);
+        let spec = match (c, n) {
+            ('y' | 'u', 2) => "%y",
+            ('y' | 'u', _) => "%Y",
+            ('M' | 'L', 1 | 2) => "%m",
+            ('M' | 'L', 3) => "%b",
+            ('M' | 'L', _) => "%B",
+            ('d', _) => "%d",
+            ('D', _) => "%j",
+            ('H' | 'k', _) => "%H",
+            ('h' | 'K', _) if twelve_hour => "%I",
+            ('h' | 'K', _) => "%H",
+            ('m', _) => "%M",
+            ('s', _) => "%S",
+            ('S', 1..=3) => "%3f",
+            ('S', 4..=6) => "%6f",
+            ('S', _) => "%9f",
+            ('a', _) => "%p",
+            ('E', 1..=3) => "%a",
+            ('E', _) => "%A",
+            ('z', _) => "%Z",
+            ('Z', _) => "%z",
+            ('X' | 'x', 1 | 2) => "%z",
+            ('X' | 'x', _) => "%:z",
+            _ => {
+                return Err(anyhow!(
+                    "unsupported letter {:?} in date format {pattern:?}",
+                    c.to_string().repeat(n)
+                ));
+            }
+        };
+        out.push_str(spec);
+        i += n;
+    }
+    Ok(out)
+}
+
+/// 引号以外是否有上下午标记 `a`
+fn has_am_pm_marker(chars: &[char]) -> bool {
+    let mut quoted = false;
+    chars.iter().any(|&c| {
+        if c == '\'' {
+            quoted = !quoted;
+        }
+        !quoted && c == 'a'
+    })
+}
+
+fn run_length(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .take_while(|&&c| c == chars[start])
+        .count()
+}
+
+fn push_literal(out: &mut String, c: char) {
+    if c == '%' {
+        out.push_str("%%");
+    } else {
+        out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc() -> Zone {
+        Zone::Fixed(Utc.fix())
+    }
+
+    fn parse(format_pattern: &str, value: &str) -> anyhow::Result<String> {
+        Ok(DateFormat::from_format_pattern(format_pattern)?
+            .parse(value, &utc())?
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
+    }
+
+    #[test]
+    fn converts_java_patterns_to_strftime() {
+        let cases = [
+            ("yyyy-MM-dd HH:mm:ss.SSS", "%Y-%m-%d %H:%M:%S%.f"),
+            ("yy/M/d H:m:s", "%y/%m/%d %H:%M:%S"),
+            ("dd MMM yyyy hh:mm a", "%d %b %Y %I:%M %p"),
+            ("EEEE, MMMM d", "%A, %B %d"),
+            ("yyyy-MM-dd'T'HH:mm:ssXXX", "%Y-%m-%dT%H:%M:%S%:z"),
+            ("HH:mm:ss Z", "%H:%M:%S %z"),
+            ("'100%' HH''mm", "100%% %H'%M"),
+            ("D HHmmssSSSSSS", "%j %H%M%S%6f"),
+        ];
+        for (java, strftime) in cases {
+            assert_eq!(java_to_strftime(java).unwrap(), strftime, "{java}");
+        }
+        assert!(java_to_strftime("yyyy 'unterminated").is_err());
+        assert!(java_to_strftime("yyyy-ww").is_err());
+    }
+
+    #[test]
+    fn hh_is_twelve_hour_only_with_an_unquoted_marker() {
+        assert_eq!(java_to_strftime("hh:mm").unwrap(), "%H:%M");
+        assert_eq!(java_to_strftime("hh:mm a").unwrap(), "%I:%M %p");
+        assert_eq!(java_to_strftime("'at' hh:mm").unwrap(), "at %H:%M");
+        assert_eq!(java_to_strftime("'it''s' hh a").unwrap(), "it's %I %p");
+    }
+
+    #[test]
+    fn parses_patterns_in_the_configured_zone() {
+        assert_eq!(
+            parse("yyyy-MM-dd HH:mm:ss.SSS", "2024-01-02 03:04:05.678").unwrap(),
+            "2024-01-02T03:04:05.678000000Z"
+        );
+        let zone = Zone::parse("+08:00").unwrap();
+        let date = DateFormat::from_format_pattern("yyyy-MM-dd")
+            .unwrap()
+            .parse("2024-01-02", &zone)
+            .unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-01-02T00:00:00+08:00");
+        assert_eq!(
+            parse("yyyy-MM-dd HH:mm:ssZ", "2024-01-02 03:04:05+0800").unwrap(),
+            "2024-01-01T19:04:05.000000000Z"
+        );
+        assert!(parse("yyyy-MM-dd", "02/01/2024").is_err());
+    }
+
+    #[test]
+    fn parses_iso8601() {
+        assert_eq!(
+            parse("iso8601", "2024-01-02T03:04:05.5+01:00").unwrap(),
+            "2024-01-02T02:04:05.500000000Z"
+        );
+        assert_eq!(
+            parse("ISO_LOCAL_DATE_TIME", "2024-01-02 03:04:05").unwrap(),
+            "2024-01-02T03:04:05.000000000Z"
+        );
+    }
+
+    #[test]
+    fn epoch_seconds_keep_every_fractional_digit() {
+        assert_eq!(
+            parse("epoch_s", "1700000000.123456789").unwrap(),
+            "2023-11-14T22:13:20.123456789Z"
+        );
+        assert_eq!(
+            parse("epoch", "1700000000.5").unwrap(),
+            "2023-11-14T22:13:20.500000000Z"
+        );
+        assert_eq!(
+            parse("epoch", "-1.5").unwrap(),
+            "1969-12-31T23:59:58.500000000Z"
+        );
+        assert_eq!(
+            parse("epoch", ".25").unwrap(),
+            "1970-01-01T00:00:00.250000000Z"
+        );
+        for invalid in ["", ".", "1e9", "1.2.3", "abc", "1.0000000001"] {
+            assert!(parse("epoch_s", invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn epoch_out_of_range_is_an_error() {
+        assert_eq!(
+            parse("epoch_ms", "1700000000123").unwrap(),
+            "2023-11-14T22:13:20.123000000Z"
+        );
+        assert_eq!(
+            parse("epoch_ns", "1").unwrap(),
+            "1970-01-01T00:00:00.000000001Z"
+        );
+        assert!(parse("epoch_ms", "9223372036854775").is_err());
+        assert!(parse("epoch_us", "-9223372036854775807").is_err());
+        assert!(parse("epoch_s", "9223372037").is_err());
+        assert!(parse("epoch_s", "99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn parses_zones() {
+        assert_eq!(Zone::parse("").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse("utc").unwrap(), Zone::Fixed(Utc.fix()));
+        assert_eq!(
+            Zone::parse("GMT-05:30").unwrap(),
+            Zone::Fixed(FixedOffset::west_opt(5 * 3600 + 1800).unwrap())
+        );
+        assert!(Zone::parse("Asia/Shanghai").is_err());
+    }
+}
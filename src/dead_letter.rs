@@ -0,0 +1,273 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::consumer::Record;
+use crate::error::{ErrorKind, ParseError};
+use crate::header::peek_subsys_code;
+use crate::resolver::Resolution;
+use crate::sink::OutputMessage;
+
+/// 解析失败或没有匹配的记录，带有原始内容时规则修复后可以重放
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub error_kind: ErrorKind,
+    pub error: String,
+    pub subsys_code: Option<String>,
+    /// 尝试过的 log_parser_rule
+    #[serde(default)]
+    pub log_parser_rule_ids: Vec<u64>,
+    /// 没有匹配的事件的行偏移，为空时整条记录都失败
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub line_offsets: Vec<usize>,
+    /// 原始记录所在的 topic/partition/offset
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: String,
+    pub timestamp: u64,
+    pub failed_at: DateTime<Local>,
+    /// 原始记录，base64 编码，其中的敏感字段没有脱敏，见 `without_raw`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "base64_bytes"
+    )]
+    pub raw: Option<Vec<u8>>,
+}
+
+impl DeadLetter {
+    /// 整条记录无法解析
+    pub fn failed(record: &Record, error: &ParseError) -> Self {
+        let subsys_code = match error {
+            ParseError::UnknownSubsys(subsys_code) => Some(subsys_code.clone()),
+            _ => peek_subsys_code(&record.value),
+        };
+        Self::new(record, error.kind(), error.to_string(), subsys_code)
+    }
+
+    /// 有事件没有匹配任何 pattern，或者没有 subsys_log_parser 匹配这个文件时返回死信
+    pub fn unmatched(record: &Record, resolution: &Resolution) -> Option<Self> {
+        let error = if resolution.log_parser_rule_ids.is_empty() {
+            "no subsys_log_parser matches this record".to_string()
+        } else {
+            let first = resolution.unmatched.first()?;
+            match resolution.unmatched.len() {
+                1 => first.to_string(),
+                n => format!("{first} and {} more", n - 1),
+            }
+        };
+        let mut dead_letter = Self::new(
+            record,
+            ErrorKind::NoMatchingPattern,
+            error,
+            Some(resolution.subsys_code.clone()),
+        );
+        dead_letter.log_parser_rule_ids = resolution.log_parser_rule_ids.clone();
+        dead_letter.line_offsets = resolution
+            .unmatched
+            .iter()
+            .filter_map(|error| match error {
+                ParseError::NoMatchingPattern { line_offset, .. } => Some(*line_offset),
+                _ => None,
+            })
+            .collect();
+        dead_letter.line_offsets.sort_unstable();
+        dead_letter.line_offsets.dedup();
+        Some(dead_letter)
+    }
+
+    fn new(
+        record: &Record,
+        error_kind: ErrorKind,
+        error: String,
+        subsys_code: Option<String>,
+    ) -> Self {
+        Self {
+            error_kind,
+            error,
+            subsys_code,
+            log_parser_rule_ids: Vec::new(),
+            line_offsets: Vec::new(),
+            topic: record.topic.clone(),
+            partition: record.partition,
+            offset: record.offset,
+            key: record.key.clone(),
+            timestamp: record.timestamp,
+            failed_at: Local::now(),
+            raw: Some(record.value.clone()),
+        }
+    }
+
+    /// 去掉原始记录，只保留错误和位置，避免未脱敏的内容进入死信
+    pub fn without_raw(mut self) -> Self {
+        self.raw = None;
+        self
+    }
+
+    /// 还原出原始记录，用于重放，没有原始记录时返回 None
+    pub fn record(&self) -> Option<Record> {
+        Some(Record {
+            key: self.key.clone(),
+            value: self.raw.clone()?,
+            timestamp: self.timestamp,
+            topic: self.topic.clone(),
+            partition: self.partition,
+            offset: self.offset,
+        })
+    }
+
+    /// 发往死信 topic 的消息，key 为原始记录的 key
+    pub fn to_message(&self, topic: &str) -> anyhow::Result<OutputMessage> {
+        Ok(OutputMessage {
+            topic: topic.to_string(),
+            key: Some(self.key.clone()).filter(|k| !k.is_empty()),
+            payload: serde_json::to_vec(self)?,
+        })
+    }
+}
+
+/// 读取 jsonl 格式的死信，`path` 为 `-` 时读取标准输入
+pub fn read_jsonl(path: &Path) -> anyhow::Result<Vec<DeadLetter>> {
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut dead_letters = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        dead_letters.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("invalid dead letter at line {}", index + 1))?,
+        );
+    }
+    Ok(dead_letters)
+}
+
+mod base64_bytes {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match bytes {
+            Some(bytes) => serializer.serialize_str(&STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|encoded| STANDARD.decode(encoded).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            key: "key".to_string(),
+            value: b"[[subsyscode=APP]]card=6222021234".to_vec(),
+            timestamp: 1,
+            topic: "raw".to_string(),
+            partition: 2,
+            offset: 3,
+        }
+    }
+
+    #[test]
+    fn raw_record_is_left_out_when_stripped() {
+        let dead_letter = DeadLetter::failed(&record(), &ParseError::MissingSubsys).without_raw();
+        let message = dead_letter.to_message("dlq").unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&message.payload).unwrap();
+        assert!(json.get("raw").is_none());
+        assert!(!String::from_utf8_lossy(&message.payload).contains("6222021234"));
+
+        let parsed: DeadLetter = serde_json::from_slice(&message.payload).unwrap();
+        assert!(parsed.raw.is_none());
+        assert!(parsed.record().is_none());
+    }
+
+    #[test]
+    fn failed_record_keeps_position_and_peeks_subsys() {
+        let dead_letter = DeadLetter::failed(&record(), &ParseError::MissingDelimiter);
+        assert_eq!(dead_letter.error_kind, ErrorKind::MissingDelimiter);
+        assert_eq!(dead_letter.subsys_code.as_deref(), Some("APP"));
+        let dead_letter =
+            DeadLetter::failed(&record(), &ParseError::UnknownSubsys("OTHER".to_string()));
+        assert_eq!(dead_letter.subsys_code.as_deref(), Some("OTHER"));
+
+        let message = dead_letter.to_message("dlq").unwrap();
+        assert_eq!(message.topic, "dlq");
+        assert_eq!(message.key.as_deref(), Some("key"));
+        let parsed: DeadLetter = serde_json::from_slice(&message.payload).unwrap();
+        let replayed = parsed.record().unwrap();
+        assert_eq!(replayed.value, record().value);
+        assert_eq!(
+            (replayed.topic.as_str(), replayed.partition, replayed.offset),
+            ("raw", 2, 3)
+        );
+    }
+
+    #[test]
+    fn unmatched_events_are_listed_by_line() {
+        let unmatched = |line_offset| ParseError::NoMatchingPattern {
+            subsys_log_parser_id: 1,
+            line_offset,
+        };
+        let mut resolution = Resolution {
+            subsys_code: "APP".to_string(),
+            log_parser_rule_ids: vec![1],
+            ..Resolution::default()
+        };
+        assert!(DeadLetter::unmatched(&record(), &resolution).is_none());
+
+        resolution.unmatched = vec![unmatched(4), unmatched(1), unmatched(4)];
+        let dead_letter = DeadLetter::unmatched(&record(), &resolution).unwrap();
+        assert_eq!(dead_letter.error_kind, ErrorKind::NoMatchingPattern);
+        assert_eq!(dead_letter.line_offsets, vec![1, 4]);
+        assert_eq!(dead_letter.log_parser_rule_ids, vec![1]);
+        assert!(dead_letter.error.ends_with("and 2 more"));
+
+        // 没有 subsys_log_parser 匹配时整条记录进入死信
+        let resolution = Resolution {
+            subsys_code: "APP".to_string(),
+            ..Resolution::default()
+        };
+        let dead_letter = DeadLetter::unmatched(&record(), &resolution).unwrap();
+        assert!(dead_letter.line_offsets.is_empty());
+    }
+
+    #[test]
+    fn reads_jsonl_skipping_blank_lines() {
+        let path = std::env::temp_dir().join(format!("dead-letters-{}.jsonl", std::process::id()));
+        let dead_letter = DeadLetter::failed(&record(), &ParseError::MissingSubsys);
+        let line = serde_json::to_string(&dead_letter).unwrap();
+        std::fs::write(&path, format!("{line}\n\n{line}\n")).unwrap();
+        let dead_letters = read_jsonl(&path);
+        std::fs::write(&path, "{}\n").unwrap();
+        let invalid = read_jsonl(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dead_letters.unwrap().len(), 2);
+        assert!(format!("{:#}", invalid.unwrap_err()).contains("line 1"));
+    }
+}
@@ -0,0 +1,387 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::Deserializer as _;
+use serde::de::{IgnoredAny, MapAccess, Visitor};
+use serde_json::{Map, Value as Json};
+
+use crate::consumer::Record;
+use crate::error::ParseError;
+use crate::header::{LogHeader, split_header};
+
+/// 记录中头部以外的信息，没有头部的记录从这里取子系统
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordContext<'a> {
+    pub key: &'a str,
+    pub topic: &'a str,
+}
+
+impl<'a> From<&'a Record> for RecordContext<'a> {
+    fn from(record: &'a Record) -> Self {
+        Self {
+            key: &record.key,
+            topic: &record.topic,
+        }
+    }
+}
+
+/// 一种头部格式
+///
+/// 每种格式都把头部转为键值对后由 `LogHeader::from_pairs` 生成头部
+pub trait HeaderDialect: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// 根据原始记录的开头判断是否是这种格式
+    fn sniff(&self, raw_log: &[u8]) -> bool;
+
+    /// 头部中的 version 是否是这种格式已知的版本，未知的版本仍然按这种格式解析，只输出警告
+    fn supports_version(&self, version: &str) -> bool;
+
+    /// 解析头部，返回头部和之后的内容
+    fn decode<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError>;
+}
+
+/// `[[k=v][k=v]]` 格式，version 为 0.1.x
+pub struct BracketDialect;
+
+impl HeaderDialect for BracketDialect {
+    fn name(&self) -> &'static str {
+        "bracket"
+    }
+
+    /// `[[` 开头，第一个键值对无法识别时仍然按这种格式解析并记录在头部的 errors 中
+    fn sniff(&self, raw_log: &[u8]) -> bool {
+        raw_log.starts_with(b"[[")
+    }
+
+    fn supports_version(&self, version: &str) -> bool {
+        version.starts_with("0.1.")
+    }
+
+    fn decode<'a>(
+        &self,
+        _context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError> {
+        let (header_bytes, content) = split_header(raw_log)?;
+        Ok((LogHeader::from_bytes(header_bytes)?, content))
+    }
+}
+
+/// 一个 JSON 对象作为头部，之后是日志内容，中间可以有一个换行，version 为 1.x
+///
+/// 嵌套的对象按 `.` 展开，例如 `{"fields0": {"k8s_pod": "a"}}` 与 `[fields0.k8s_pod=a]` 相同
+pub struct JsonDialect;
+
+impl HeaderDialect for JsonDialect {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    /// `{` 开头并且第一层有 subsyscode 或 version 键，其他 JSON 开头的记录交给之后的格式
+    fn sniff(&self, raw_log: &[u8]) -> bool {
+        if raw_log.first() != Some(&b'{') {
+            return false;
+        }
+        // 找到键之后 JSON 不合法仍然按这种格式解析，在 decode 中报告 InvalidJsonHeader
+        let found = Cell::new(false);
+        let _ = serde_json::Deserializer::from_slice(raw_log).deserialize_map(EnvelopeKeys(&found));
+        found.get()
+    }
+
+    fn supports_version(&self, version: &str) -> bool {
+        version.starts_with("1.")
+    }
+
+    fn decode<'a>(
+        &self,
+        _context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError> {
+        let mut stream =
+            serde_json::Deserializer::from_slice(raw_log).into_iter::<Map<String, Json>>();
+        let header = match stream.next() {
+            Some(Ok(header)) => header,
+            Some(Err(error)) => return Err(ParseError::InvalidJsonHeader(error)),
+            None => return Err(ParseError::MissingDelimiter),
+        };
+        let content = &raw_log[stream.byte_offset()..];
+        let content = content
+            .strip_prefix(b"\r\n")
+            .or_else(|| content.strip_prefix(b"\n"))
+            .unwrap_or(content);
+        let mut pairs = HashMap::new();
+        flatten("", header, &mut pairs);
+        Ok((LogHeader::from_pairs(pairs, Vec::new())?, content))
+    }
+}
+
+/// 逐个检查 JSON 头部第一层的键，遇到 subsyscode 或 version 即停止
+struct EnvelopeKeys<'a>(&'a Cell<bool>);
+
+impl<'de> Visitor<'de> for EnvelopeKeys<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "subsyscode" || key == "version" {
+                self.0.set(true);
+                return Ok(());
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+}
+
+fn flatten(prefix: &str, object: Map<String, Json>, pairs: &mut HashMap<String, String>) {
+    for (key, value) in object {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            Json::Null => {}
+            Json::String(value) => {
+                pairs.insert(key, value);
+            }
+            Json::Object(object) => flatten(&key, object, pairs),
+            value => {
+                pairs.insert(key, value.to_string());
+            }
+        }
+    }
+}
+
+/// 没有头部的记录的子系统从哪里取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubsysSource {
+    /// 记录的 key，为空时使用 topic
+    Key,
+    Topic,
+}
+
+impl FromStr for SubsysSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "key" => Ok(SubsysSource::Key),
+            "topic" => Ok(SubsysSource::Topic),
+            _ => Err(anyhow!("unknown subsys source {s}, expected key or topic")),
+        }
+    }
+}
+
+/// 没有头部，整条记录都是 UTF-8 的日志内容
+pub struct HeaderlessDialect {
+    pub subsys_from: SubsysSource,
+}
+
+impl HeaderDialect for HeaderlessDialect {
+    fn name(&self) -> &'static str {
+        "headerless"
+    }
+
+    fn sniff(&self, _raw_log: &[u8]) -> bool {
+        true
+    }
+
+    fn supports_version(&self, _version: &str) -> bool {
+        true
+    }
+
+    fn decode<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError> {
+        let subsys_code = match self.subsys_from {
+            SubsysSource::Key if !context.key.is_empty() => context.key,
+            _ => context.topic,
+        };
+        let mut pairs = HashMap::new();
+        if !subsys_code.is_empty() {
+            pairs.insert("subsyscode".to_string(), subsys_code.to_string());
+        }
+        Ok((LogHeader::from_pairs(pairs, Vec::new())?, raw_log))
+    }
+}
+
+/// 按顺序尝试的头部格式，使用第一个 `sniff` 匹配的格式
+pub struct HeaderDialects {
+    dialects: Vec<Box<dyn HeaderDialect>>,
+}
+
+/// 默认支持 `BracketDialect` 和 `JsonDialect`，没有头部的记录返回 `ParseError::MissingDelimiter`
+impl Default for HeaderDialects {
+    fn default() -> Self {
+        Self {
+            dialects: vec![Box::new(BracketDialect), Box::new(JsonDialect)],
+        }
+    }
+}
+
+impl HeaderDialects {
+    pub fn empty() -> Self {
+        Self {
+            dialects: Vec::new(),
+        }
+    }
+
+    pub fn register(mut self, dialect: impl HeaderDialect + 'static) -> Self {
+        self.dialects.push(Box::new(dialect));
+        self
+    }
+
+    /// 其他格式都不匹配时作为没有头部的记录处理
+    pub fn with_headerless(self, subsys_from: SubsysSource) -> Self {
+        self.register(HeaderlessDialect { subsys_from })
+    }
+
+    /// 解析头部，返回头部和之后的内容，头部的 version 未知时输出警告
+    pub fn decode<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError> {
+        let dialect = self
+            .dialects
+            .iter()
+            .find(|dialect| dialect.sniff(raw_log))
+            .ok_or(ParseError::MissingDelimiter)?;
+        let (header, content) = dialect.decode(context, raw_log)?;
+        if let Some(version) = &header.version
+            && !dialect.supports_version(version)
+        {
+            log::warn!(
+                "unknown {} log header version {version} from {}",
+                dialect.name(),
+                header.subsys_code
+            );
+        }
+        Ok((header, content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::HeaderError;
+
+    use super::*;
+
+    fn context<'a>(key: &'a str, topic: &'a str) -> RecordContext<'a> {
+        RecordContext { key, topic }
+    }
+
+    #[test]
+    fn sniffs_bracket_and_json_headers() {
+        let dialects = HeaderDialects::default();
+        let (header, content) = dialects
+            .decode(context("", ""), b"[[subsyscode=APP][version=0.1.2]]log")
+            .unwrap();
+        assert_eq!((header.subsys_code.as_str(), content), ("APP", &b"log"[..]));
+
+        let raw =
+            br#"{"subsyscode": "APP", "file_offset": 10, "fields0": {"k8s_pod": "p"}, "x": null}
+log"#;
+        let (header, content) = dialects.decode(context("", ""), raw).unwrap();
+        assert_eq!(header.subsys_code, "APP");
+        assert_eq!(header.file_offset, Some(10));
+        assert_eq!(header.attr["fields0.k8s_pod"].as_str(), Some("p"));
+        assert!(!header.attr.contains_key("x"));
+        assert_eq!(content, b"log");
+    }
+
+    #[test]
+    fn records_without_header_need_headerless_dialect() {
+        let raw = b"[main] INFO plain line";
+        assert!(matches!(
+            HeaderDialects::default().decode(context("key", "topic"), raw),
+            Err(ParseError::MissingDelimiter)
+        ));
+
+        let dialects = HeaderDialects::default().with_headerless(SubsysSource::Key);
+        let (header, content) = dialects.decode(context("KEY", "TOPIC"), raw).unwrap();
+        assert_eq!((header.subsys_code.as_str(), content), ("KEY", &raw[..]));
+        let (header, _) = dialects.decode(context("", "TOPIC"), raw).unwrap();
+        assert_eq!(header.subsys_code, "TOPIC");
+
+        let dialects = HeaderDialects::default().with_headerless(SubsysSource::Topic);
+        let (header, _) = dialects.decode(context("KEY", "TOPIC"), raw).unwrap();
+        assert_eq!(header.subsys_code, "TOPIC");
+    }
+
+    #[test]
+    fn unknown_versions_are_accepted() {
+        let dialects = HeaderDialects::default();
+        let (header, _) = dialects
+            .decode(context("", ""), b"[[subsyscode=APP][version=2.0.0]]log")
+            .unwrap();
+        assert_eq!(header.version.as_deref(), Some("2.0.0"));
+        let (header, _) = dialects
+            .decode(
+                context("", ""),
+                br#"{"subsyscode": "APP", "version": "0.1.2"}log"#,
+            )
+            .unwrap();
+        assert_eq!(header.version.as_deref(), Some("0.1.2"));
+    }
+
+    #[test]
+    fn malformed_first_pair_is_a_header_error() {
+        let (header, content) = HeaderDialects::default()
+            .decode(context("", ""), b"[[encode-UTF-8][subsyscode=APP]]log")
+            .unwrap();
+        assert_eq!((header.subsys_code.as_str(), content), ("APP", &b"log"[..]));
+        assert!(matches!(
+            header.errors.as_slice(),
+            [HeaderError::MalformedPair { offset: 1, .. }]
+        ));
+        assert!(matches!(
+            HeaderDialects::default().decode(context("", ""), b"{\"subsyscode\": }log"),
+            Err(ParseError::InvalidJsonHeader(_))
+        ));
+    }
+
+    #[test]
+    fn json_without_envelope_keys_is_headerless() {
+        let raw = br#"{"msg": "x", "fields": {"version": "1.0"}}"#;
+        assert!(matches!(
+            HeaderDialects::default().decode(context("", "TOPIC"), raw),
+            Err(ParseError::MissingDelimiter)
+        ));
+        let dialects = HeaderDialects::default().with_headerless(SubsysSource::Topic);
+        let (header, content) = dialects.decode(context("", "TOPIC"), raw).unwrap();
+        assert_eq!(header.subsys_code, "TOPIC");
+        assert_eq!(content, &raw[..]);
+        let (_, content) = dialects
+            .decode(context("", "TOPIC"), br#"{"msg":"x"}"#)
+            .unwrap();
+        assert_eq!(content, br#"{"msg":"x"}"#);
+    }
+
+    #[test]
+    fn registered_dialects_are_tried_in_order() {
+        let dialects = HeaderDialects::empty().with_headerless(SubsysSource::Topic);
+        let (header, content) = dialects
+            .decode(context("", "TOPIC"), b"[[subsyscode=APP]]log")
+            .unwrap();
+        assert_eq!(header.subsys_code, "TOPIC");
+        assert_eq!(content, b"[[subsyscode=APP]]log");
+        assert!("KEY".parse::<SubsysSource>().is_ok());
+        assert!("value".parse::<SubsysSource>().is_err());
+    }
+}
@@ -1,61 +1,297 @@
 use std::{error::Error, fmt};
 
+use serde::{Deserialize, Serialize};
+
+type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+/// 解析一条记录时可能出现的错误
+///
+/// 头部、编码和子系统的错误使整条记录无法解析，由 `Resolver::resolve` 返回；
+/// 没有匹配的事件和字段转换失败只影响单个事件，记录在解析结果中
 #[derive(Debug)]
-pub struct ParseError {
-    message: String,
-    source: Option<Box<dyn Error + Send + Sync + 'static>>,
+pub enum ParseError {
+    /// 找不到头部和内容之间的 `]]`
+    MissingDelimiter,
+    /// 头部不是 UTF-8
+    InvalidHeader(std::str::Utf8Error),
+    /// JSON 格式的头部无法解析
+    InvalidJsonHeader(serde_json::Error),
+    /// 头部中无法识别的键值对或无法解析的值，只影响对应的字段
+    Header(HeaderError),
+    /// 头部的 encode 无法识别
+    UnknownEncoding(String),
+    /// 头部的 compress_algorithm 无法识别
+    UnknownCompression(String),
+    /// 压缩的内容已损坏
+    Decompression {
+        algorithm: &'static str,
+        source: BoxError,
+    },
+    /// 解压后超过了大小限制
+    DecompressedTooLarge {
+        algorithm: &'static str,
+        limit: usize,
+    },
+    /// 子系统不在 sys_subsys_config 中
+    UnknownSubsys(String),
+    /// 按配置的来源都找不到子系统，且没有配置 fallback
+    MissingSubsys,
+    /// 事件没有匹配任何 pattern
+    NoMatchingPattern {
+        subsys_log_parser_id: u64,
+        line_offset: usize,
+    },
+    /// 字段无法转换为配置的类型
+    FieldConversion {
+        field: String,
+        log_parser_field_id: u64,
+        source: BoxError,
+    },
+    /// 规则存储无法访问，记录没有被解析，可以稍后重试
+    RepositoryUnavailable(BoxError),
 }
 
-// 2. 实现 Display trait，用于用户友好的错误信息展示
-impl fmt::Display for ParseError {
+/// 头部中的一个问题，跳过对应的键值对后头部仍然可以使用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// 无法识别的键值对，例如 `[encode-UTF-8]` 缺少 `=`，`offset` 是在头部中的字节位置
+    MalformedPair {
+        offset: usize,
+        text: String,
+        reason: String,
+    },
+    /// 值无法解析，例如 file_offset 不是数字、ip 不是合法的地址
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for HeaderError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "自定义错误: {}", self.message)?;
-        if let Some(source) = &self.source {
-            write!(f, "\n  原因: {}", source)?;
+        match self {
+            HeaderError::MalformedPair {
+                offset,
+                text,
+                reason,
+            } => write!(
+                f,
+                "malformed header pair [{text}] at byte {offset}: {reason}"
+            ),
+            HeaderError::InvalidValue { key, value, reason } => {
+                write!(f, "invalid header field {key}={value:?}: {reason}")
+            }
         }
-        Ok(())
     }
 }
 
-// 3. 实现 Error trait，这是将你的类型标记为错误的核心
-impl Error for ParseError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        // 如果你的错误是由另一个错误引起的，这里可以返回那个错误的引用
-        // 注意这里的类型转换
-        self.source
-            .as_ref()
-            .map(|b| b.as_ref() as &(dyn Error + 'static))
-    }
+/// 错误的类别，用于计数、路由和告警
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    MissingDelimiter,
+    InvalidHeader,
+    MalformedHeaderPair,
+    InvalidHeaderField,
+    UnknownEncoding,
+    UnknownCompression,
+    Decompression,
+    DecompressedTooLarge,
+    UnknownSubsys,
+    MissingSubsys,
+    NoMatchingPattern,
+    FieldConversion,
+    RepositoryUnavailable,
+}
 
-    // description() 方法已废弃，通常不需要实现
-    // fn description(&self) -> &str {
-    //     &self.message
-    // }
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::MissingDelimiter => "missing_delimiter",
+            ErrorKind::InvalidHeader => "invalid_header",
+            ErrorKind::MalformedHeaderPair => "malformed_header_pair",
+            ErrorKind::InvalidHeaderField => "invalid_header_field",
+            ErrorKind::UnknownEncoding => "unknown_encoding",
+            ErrorKind::UnknownCompression => "unknown_compression",
+            ErrorKind::Decompression => "decompression",
+            ErrorKind::DecompressedTooLarge => "decompressed_too_large",
+            ErrorKind::UnknownSubsys => "unknown_subsys",
+            ErrorKind::MissingSubsys => "missing_subsys",
+            ErrorKind::NoMatchingPattern => "no_matching_pattern",
+            ErrorKind::FieldConversion => "field_conversion",
+            ErrorKind::RepositoryUnavailable => "repository_unavailable",
+        }
+    }
+}
 
-    // cause() 方法也已废弃，被 source() 取代
-    // fn cause(&self) -> Option<&dyn Error> {
-    //     self.source.as_ref().map(|b| b.as_ref())
-    // }
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-// 4. (可选) 为你的错误类型提供一些构造函数，使其更易于创建
 impl ParseError {
-    // 创建一个简单的错误
-    pub fn new(message: &str) -> Self {
-        ParseError {
-            message: message.to_string(),
-            source: None,
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ParseError::MissingDelimiter => ErrorKind::MissingDelimiter,
+            ParseError::InvalidHeader(_) | ParseError::InvalidJsonHeader(_) => {
+                ErrorKind::InvalidHeader
+            }
+            ParseError::Header(HeaderError::MalformedPair { .. }) => ErrorKind::MalformedHeaderPair,
+            ParseError::Header(HeaderError::InvalidValue { .. }) => ErrorKind::InvalidHeaderField,
+            ParseError::UnknownEncoding(_) => ErrorKind::UnknownEncoding,
+            ParseError::UnknownCompression(_) => ErrorKind::UnknownCompression,
+            ParseError::Decompression { .. } => ErrorKind::Decompression,
+            ParseError::DecompressedTooLarge { .. } => ErrorKind::DecompressedTooLarge,
+            ParseError::UnknownSubsys(_) => ErrorKind::UnknownSubsys,
+            ParseError::MissingSubsys => ErrorKind::MissingSubsys,
+            ParseError::NoMatchingPattern { .. } => ErrorKind::NoMatchingPattern,
+            ParseError::FieldConversion { .. } => ErrorKind::FieldConversion,
+            ParseError::RepositoryUnavailable(_) => ErrorKind::RepositoryUnavailable,
         }
     }
 
-    // 创建一个包装了其他错误的错误
-    pub fn with_source<E>(message: &str, source: E) -> Self
+    pub fn repository<E>(source: E) -> Self
     where
-        E: Error + Send + Sync + 'static,
+        E: Into<BoxError>,
     {
-        ParseError {
-            message: message.to_string(),
-            source: Some(Box::new(source)),
+        ParseError::RepositoryUnavailable(source.into())
+    }
+
+    /// `error` 或者它的 context 之下是否是规则存储无法访问
+    pub fn is_repository_unavailable(error: &anyhow::Error) -> bool {
+        error
+            .downcast_ref::<ParseError>()
+            .is_some_and(|error| error.kind() == ErrorKind::RepositoryUnavailable)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingDelimiter => write!(f, "log header delimiter not found"),
+            ParseError::InvalidHeader(error) => write!(f, "log header is not UTF-8: {error}"),
+            ParseError::InvalidJsonHeader(error) => write!(f, "invalid JSON log header: {error}"),
+            ParseError::Header(error) => write!(f, "{error}"),
+            ParseError::UnknownEncoding(label) => write!(f, "unknown encoding {label}"),
+            ParseError::UnknownCompression(label) => {
+                write!(f, "unknown compress_algorithm {label}")
+            }
+            ParseError::Decompression { algorithm, source } => {
+                write!(f, "failed to decompress {algorithm} content: {source}")
+            }
+            ParseError::DecompressedTooLarge { algorithm, limit } => write!(
+                f,
+                "{algorithm} content exceeds {limit} bytes after decompression"
+            ),
+            ParseError::UnknownSubsys(subsys_code) => write!(f, "unknown subsys {subsys_code}"),
+            ParseError::MissingSubsys => write!(f, "no subsys code found for this record"),
+            ParseError::NoMatchingPattern {
+                subsys_log_parser_id,
+                line_offset,
+            } => write!(
+                f,
+                "no pattern of subsys_log_parser {subsys_log_parser_id} matched event at line {line_offset}"
+            ),
+            ParseError::FieldConversion {
+                field,
+                log_parser_field_id,
+                source,
+            } => write!(
+                f,
+                "failed to parse field {field} of log_parser_field {log_parser_field_id}: {source}"
+            ),
+            ParseError::RepositoryUnavailable(error) => {
+                write!(f, "rule repository unavailable: {error}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::InvalidHeader(error) => Some(error),
+            ParseError::InvalidJsonHeader(error) => Some(error),
+            ParseError::Decompression { source, .. } => Some(source.as_ref()),
+            ParseError::FieldConversion { source, .. } => Some(source.as_ref()),
+            ParseError::RepositoryUnavailable(error) => Some(error.as_ref()),
+            _ => None,
         }
     }
 }
+
+impl Serialize for ParseError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_serialize_as_their_names() {
+        let errors = [
+            ParseError::MissingDelimiter,
+            ParseError::Header(HeaderError::MalformedPair {
+                offset: 1,
+                text: "a".to_string(),
+                reason: "missing =".to_string(),
+            }),
+            ParseError::Header(HeaderError::InvalidValue {
+                key: "file_offset".to_string(),
+                value: "x".to_string(),
+                reason: "not a number".to_string(),
+            }),
+            ParseError::DecompressedTooLarge {
+                algorithm: "gzip",
+                limit: 10,
+            },
+            ParseError::NoMatchingPattern {
+                subsys_log_parser_id: 1,
+                line_offset: 2,
+            },
+            ParseError::repository("connection refused"),
+        ];
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            serde_json::to_string(&kinds).unwrap(),
+            r#"["missing_delimiter","malformed_header_pair","invalid_header_field","decompressed_too_large","no_matching_pattern","repository_unavailable"]"#
+        );
+        for kind in kinds {
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(json, format!("\"{kind}\""));
+            assert_eq!(serde_json::from_str::<ErrorKind>(&json).unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn errors_serialize_as_their_messages() {
+        let error = ParseError::FieldConversion {
+            field: "latency".to_string(),
+            log_parser_field_id: 3,
+            source: "\"x\" is not an integer".into(),
+        };
+        assert_eq!(error.kind(), ErrorKind::FieldConversion);
+        assert!(error.source().is_some());
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#""failed to parse field latency of log_parser_field 3: \"x\" is not an integer""#
+        );
+        assert_eq!(
+            ParseError::Header(HeaderError::MalformedPair {
+                offset: 5,
+                text: "encode-UTF-8".to_string(),
+                reason: "missing =".to_string(),
+            })
+            .to_string(),
+            "malformed header pair [encode-UTF-8] at byte 5: missing ="
+        );
+    }
+}
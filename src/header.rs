@@ -0,0 +1,622 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use encoding_rs::Encoding;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde::ser::SerializeStruct;
+use serde_json::Map;
+
+use crate::error::{HeaderError, ParseError};
+use crate::metadata::Kubernetes;
+use crate::util::is_empty;
+use crate::value::Value;
+
+/// 值中需要转义的字符，键中还需要转义 `=`
+const ESCAPED: &[u8] = b"[]=\\";
+
+/// 头部中的一段内容
+enum Token<'a> {
+    /// `[...]` 中的内容，`offset` 是 `[` 在头部中的位置
+    Pair { offset: usize, text: &'a [u8] },
+    /// 键值对之间多余的内容
+    Stray { offset: usize, text: &'a [u8] },
+}
+
+/// 按头部的语法切分，返回头部的长度(包含结尾的 `]]`)和其中的各段内容
+///
+/// 头部为 `[[k=v][k=v]]`，必须以 `[[` 开头；`\` 转义下一个 `[`、`]`、`=` 或 `\`，
+/// 值中成对的 `[]` 不需要转义，在其他字符前的 `\` 按原样保留，例如 `(\r|\n)`
+fn scan(raw_log: &[u8]) -> Result<(usize, Vec<Token<'_>>), ParseError> {
+    if !raw_log.starts_with(b"[[") {
+        return Err(ParseError::MissingDelimiter);
+    }
+    let mut i = 1;
+    let mut tokens = Vec::new();
+    loop {
+        match raw_log.get(i) {
+            None => return Err(ParseError::MissingDelimiter),
+            Some(b']') => return Ok((i + 1, tokens)),
+            Some(b'[') => {
+                let start = i + 1;
+                let mut end = start;
+                let mut depth = 0usize;
+                loop {
+                    match raw_log.get(end) {
+                        None => return Err(ParseError::MissingDelimiter),
+                        Some(b'\\') => end += 2,
+                        Some(b']') if depth == 0 => break,
+                        Some(b']') => {
+                            depth -= 1;
+                            end += 1;
+                        }
+                        Some(b'[') => {
+                            depth += 1;
+                            end += 1;
+                        }
+                        Some(_) => end += 1,
+                    }
+                }
+                tokens.push(Token::Pair {
+                    offset: i,
+                    text: &raw_log[start..end],
+                });
+                i = end + 1;
+            }
+            Some(_) => {
+                let end = raw_log[i..]
+                    .iter()
+                    .position(|b| matches!(b, b'[' | b']'))
+                    .map_or(raw_log.len(), |pos| i + pos);
+                tokens.push(Token::Stray {
+                    offset: i,
+                    text: &raw_log[i..end],
+                });
+                i = end;
+            }
+        }
+    }
+}
+
+/// 去掉转义，`\` 后面不是需要转义的字符时原样保留
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\'
+            && let Some(&next) = chars.peek()
+            && next.is_ascii()
+            && ESCAPED.contains(&(next as u8))
+        {
+            out.push(next);
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn escape(s: &str, out: &mut String, escape_eq: bool) {
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' | ']' => out.push('\\'),
+            '=' if escape_eq => out.push('\\'),
+            // 只有会被当作转义的 `\` 才需要转义
+            '\\' if chars
+                .peek()
+                .is_none_or(|&next| next.is_ascii() && ESCAPED.contains(&(next as u8))) =>
+            {
+                out.push('\\')
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+}
+
+/// 把键值对编码为头部，包含结尾的 `]]`，`LogHeader::from_bytes` 可以原样解析回来
+///
+/// ```
+/// # use log_resolver_rs::header::{LogHeader, encode_header};
+/// let header = encode_header([("subsyscode", "demo"), ("pattern", "\\[(\\d+)\\]")]);
+/// assert_eq!(header, r"[[subsyscode=demo][pattern=\\\[(\d+)\\\]]]");
+/// let log_header = LogHeader::from_bytes(header.as_bytes())?;
+/// assert_eq!(log_header.attr["pattern"].as_str(), Some("\\[(\\d+)\\]"));
+/// # anyhow::Ok(())
+/// ```
+pub fn encode_header<K, V>(pairs: impl IntoIterator<Item = (K, V)>) -> String
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut header = String::from("[");
+    for (k, v) in pairs {
+        header.push('[');
+        escape(k.as_ref(), &mut header, true);
+        header.push('=');
+        escape(v.as_ref(), &mut header, false);
+        header.push(']');
+    }
+    header.push(']');
+    header
+}
+
+/// 解析头部中的键值对，同一个键出现多次时取最后一个；
+/// 无法识别的键值对跳过，记录在返回的错误中
+fn parse_header_kv(
+    header: &str,
+) -> Result<(HashMap<String, String>, Vec<HeaderError>), ParseError> {
+    let (_, tokens) = scan(header.as_bytes())?;
+    let mut attributes = HashMap::new();
+    let mut errors = Vec::new();
+    for token in tokens {
+        // 切分的位置都在 ASCII 字符处，仍然是合法的 UTF-8
+        let (offset, text, pair) = match token {
+            Token::Pair { offset, text } => (offset, text, true),
+            Token::Stray { offset, text } => (offset, text, false),
+        };
+        let text = String::from_utf8_lossy(text);
+        let malformed = |reason: &str| HeaderError::MalformedPair {
+            offset,
+            text: text.to_string(),
+            reason: reason.to_string(),
+        };
+        if !pair {
+            errors.push(malformed("unexpected text between pairs"));
+            continue;
+        }
+        let Some(eq) = find_unescaped_eq(&text) else {
+            errors.push(malformed("missing '='"));
+            continue;
+        };
+        let key = unescape(&text[..eq]);
+        if key.is_empty() {
+            errors.push(malformed("empty key"));
+            continue;
+        }
+        let value = unescape(&text[eq + 1..]);
+        log::debug!("key: {key}, value: {value}");
+        attributes.insert(key, value);
+    }
+    Ok((attributes, errors))
+}
+
+fn find_unescaped_eq(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'=' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// 按头部的语法把原始日志分成头部和内容，头部包含结尾的 `]]`
+pub fn split_header(raw_log: &[u8]) -> Result<(&[u8], &[u8]), ParseError> {
+    let (len, _) = scan(raw_log)?;
+    Ok(raw_log.split_at(len))
+}
+
+/// 日志块的头部
+///
+/// 采集端写入的常用键解析为带类型的字段，其余的键保留在 attr 中；
+/// 值无法解析的键也原样保留在 attr 中，并记录在 errors 里
+#[derive(Debug, Clone)]
+pub struct LogHeader {
+    /// 按 `DEFAULT_SUBSYS_KEYS` 取出的子系统，没有时为空，`Resolver` 会按配置的 `SubsysChain` 重新确定
+    pub subsys_code: String,
+    pub encode: &'static encoding_rs::Encoding,
+    pub hostname: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub filename: Option<String>,
+    pub path: Option<String>,
+    /// 日志块在文件中的字节偏移
+    pub file_offset: Option<u64>,
+    /// 日志块第一行在文件中的行号
+    pub file_line: Option<u64>,
+    /// 日志块压缩前的字节数
+    pub data_length: Option<u64>,
+    /// 日志块在文件中的序号
+    pub block_index: Option<u64>,
+    /// 采集端配置的 topic
+    pub topic: Option<String>,
+    /// 采集端的版本
+    pub version: Option<String>,
+    /// 其余的键值对和解析出的字段
+    pub attr: HashMap<String, Value>,
+    /// `fields0.*` 等键嵌套后的结构，由 `KeyMapping` 生成
+    pub metadata: Map<String, serde_json::Value>,
+    /// 由 `KeyMapping` 从头部取出的 kubernetes 元数据
+    pub kubernetes: Kubernetes,
+    /// 头部中无法识别的键值对和无法解析的值
+    pub errors: Vec<HeaderError>,
+}
+
+impl Serialize for LogHeader {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("LogHeader", 15)?;
+        state.serialize_field("subsys_code", &self.subsys_code)?;
+        state.serialize_field("encode", self.encode.name())?;
+        state.serialize_field("hostname", &self.hostname)?;
+        state.serialize_field("ip", &self.ip)?;
+        state.serialize_field("filename", &self.filename)?;
+        state.serialize_field("path", &self.path)?;
+        state.serialize_field("file_offset", &self.file_offset)?;
+        state.serialize_field("file_line", &self.file_line)?;
+        state.serialize_field("data_length", &self.data_length)?;
+        state.serialize_field("block_index", &self.block_index)?;
+        state.serialize_field("topic", &self.topic)?;
+        state.serialize_field("version", &self.version)?;
+        state.serialize_field("metadata", &self.metadata)?;
+        state.serialize_field("kubernetes", &self.kubernetes)?;
+        // 兼容原来的输出格式，attr 中仍然包含头部中的全部键
+        let mut attr: HashMap<&str, Value> = self
+            .known_fields()
+            .into_iter()
+            .map(|(k, v)| (k, Value::from(v)))
+            .collect();
+        attr.extend(self.attr.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        state.serialize_field("attr", &attr)?;
+        state.end()
+    }
+}
+
+impl LogHeader {
+    /// 解析头部，`header_bytes` 包含结尾的 `]]`
+    pub fn from_bytes(header_bytes: &[u8]) -> Result<Self, ParseError> {
+        let header_str = std::str::from_utf8(header_bytes).map_err(ParseError::InvalidHeader)?;
+        log::debug!("header: {:?}", header_str);
+        let (headers, errors) = parse_header_kv(header_str)?;
+        Self::from_pairs(headers, errors)
+    }
+
+    /// 由头部的键值对生成头部，各种格式的头部都转为键值对后由这里生成，
+    /// `errors` 是解析键值对时已经发现的问题
+    pub fn from_pairs(
+        mut headers: HashMap<String, String>,
+        mut errors: Vec<HeaderError>,
+    ) -> Result<Self, ParseError> {
+        let encoding_label_opt = headers.get("encode").map(|s| s.to_string());
+
+        let encoding_label = encoding_label_opt.as_deref().unwrap_or("UTF-8");
+
+        let encoding = get_encoding_from_label(encoding_label)
+            .ok_or_else(|| ParseError::UnknownEncoding(encoding_label.to_string()))?;
+
+        let subsys_code = get_subsys_code(&headers).unwrap_or_default();
+        log::debug!("{subsys_code}");
+
+        Ok(LogHeader {
+            subsys_code,
+            encode: encoding,
+            hostname: take(&mut headers, "hostname"),
+            ip: take_parsed(&mut headers, "ip", &mut errors),
+            filename: take(&mut headers, "filename"),
+            path: take(&mut headers, "path"),
+            file_offset: take_parsed(&mut headers, "file_offset", &mut errors),
+            file_line: take_parsed(&mut headers, "file_line", &mut errors),
+            data_length: take_parsed(&mut headers, "data_length", &mut errors),
+            block_index: take_parsed(&mut headers, "block_index", &mut errors),
+            topic: take(&mut headers, "topic"),
+            version: take(&mut headers, "version"),
+            attr: headers
+                .into_iter()
+                .map(|(k, v)| (k, Value::from(v)))
+                .collect(),
+            metadata: Map::new(),
+            kubernetes: Kubernetes::default(),
+            errors,
+        })
+    }
+
+    /// 按键排序写回 `[k=v]...]` 形式的头部，包含结尾的 `]]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut attr: Vec<(&str, String)> = self.known_fields();
+        attr.extend(self.attr.iter().map(|(k, v)| (k.as_str(), v.to_string())));
+        attr.sort_by_key(|(k, _)| *k);
+        encode_header(attr).into_bytes()
+    }
+
+    /// 日志所在的文件，以 (hostname, path) 区分，没有 path 时为 None
+    pub fn source_file(&self) -> Option<(String, String)> {
+        let path = self.path.clone()?;
+        Some((self.hostname.clone().unwrap_or_default(), path))
+    }
+
+    /// 头部中的值，`key` 可以是带类型的字段、attr 中的键或者 `kubernetes.pod` 等映射出的元数据
+    pub fn get(&self, key: &str) -> Option<String> {
+        if let Some(field) = key.strip_prefix("kubernetes.") {
+            let kubernetes = &self.kubernetes;
+            return match field {
+                "pod" => kubernetes.pod.clone(),
+                "namespace" => kubernetes.namespace.clone(),
+                "node" => kubernetes.node.clone(),
+                "container" => kubernetes.container.clone(),
+                "uid" => kubernetes.uid.clone(),
+                _ => None,
+            };
+        }
+        self.known_fields()
+            .into_iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+            .or_else(|| self.attr.get(key).map(Value::to_string))
+    }
+
+    /// 有值的带类型字段，按头部中的写法转为字符串
+    fn known_fields(&self) -> Vec<(&'static str, String)> {
+        let strings = [
+            ("hostname", &self.hostname),
+            ("filename", &self.filename),
+            ("path", &self.path),
+            ("topic", &self.topic),
+            ("version", &self.version),
+        ];
+        let numbers = [
+            ("file_offset", self.file_offset),
+            ("file_line", self.file_line),
+            ("data_length", self.data_length),
+            ("block_index", self.block_index),
+        ];
+        let mut fields: Vec<_> = strings
+            .into_iter()
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k, v.clone())))
+            .chain(
+                numbers
+                    .into_iter()
+                    .filter_map(|(k, v)| v.map(|v| (k, v.to_string()))),
+            )
+            .collect();
+        if let Some(ip) = self.ip {
+            fields.push(("ip", ip.to_string()));
+        }
+        fields
+    }
+}
+
+/// 取出头部中的字符串，空值视为没有
+fn take(headers: &mut HashMap<String, String>, key: &str) -> Option<String> {
+    headers.remove(key).filter(|v| !v.is_empty())
+}
+
+/// 取出并解析头部中的值，无法解析时把原值留在 `headers` 中并记录错误
+fn take_parsed<T>(
+    headers: &mut HashMap<String, String>,
+    key: &str,
+    errors: &mut Vec<HeaderError>,
+) -> Option<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = take(headers, key)?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(error) => {
+            errors.push(HeaderError::InvalidValue {
+                key: key.to_string(),
+                value: value.clone(),
+                reason: error.to_string(),
+            });
+            headers.insert(key.to_string(), value);
+            None
+        }
+    }
+}
+
+/// 尽量从原始日志中取出子系统，头部无法完整解析时也可以使用
+pub fn peek_subsys_code(raw_log: &[u8]) -> Option<String> {
+    let (header_bytes, _) = split_header(raw_log).ok()?;
+    let (headers, _) = parse_header_kv(&String::from_utf8_lossy(header_bytes)).ok()?;
+    get_subsys_code(&headers)
+}
+
+/// 默认按顺序从这些键中取子系统
+pub const DEFAULT_SUBSYS_KEYS: [&str; 2] = ["fields0.SUBSYSCODE", "subsyscode"];
+
+/// 空值和 `null` 视为没有
+fn get_subsys_code(headers: &HashMap<String, String>) -> Option<String> {
+    DEFAULT_SUBSYS_KEYS
+        .iter()
+        .filter_map(|key| headers.get(*key))
+        .find(|value| !is_empty(value))
+        .map(|value| value.trim().to_string())
+}
+
+// 预定义常见的编码标签映射 (可选，提高查找效率)
+static ENCODING_MAP: Lazy<HashMap<&'static str, &'static Encoding>> = Lazy::new(|| {
+    let mut m = HashMap::new();
+    m.insert("utf-8", encoding_rs::UTF_8);
+    m.insert("utf8", encoding_rs::UTF_8);
+    m.insert("gbk", encoding_rs::GBK);
+    m.insert("gb18030", encoding_rs::GB18030);
+    // 添加其他你可能需要支持的编码
+    m.insert("latin1", encoding_rs::WINDOWS_1252); // alias for latin1
+    m.insert("windows-1252", encoding_rs::WINDOWS_1252);
+    m
+});
+
+// 查找编码的辅助函数
+pub fn get_encoding_from_label(label: &str) -> Option<&'static Encoding> {
+    // 优先使用预定义 Map (更快)
+    if let Some(encoding) = ENCODING_MAP.get(label.to_lowercase().as_str()) {
+        return Some(encoding);
+    }
+    // 其次尝试 encoding_rs 的动态查找
+    Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_typed_fields() {
+        let header = LogHeader::from_bytes(
+            b"[[subsyscode=APP][encode=GBK][hostname=host][ip=10.0.0.1][filename=a.log]\
+[path=/var/a.log][file_offset=100][file_line=7][data_length=20][block_index=3]\
+[topic=raw][version=0.1.2][custom=x]]",
+        )
+        .unwrap();
+        assert_eq!(header.subsys_code, "APP");
+        assert_eq!(header.encode, encoding_rs::GBK);
+        assert_eq!(header.hostname.as_deref(), Some("host"));
+        assert_eq!(header.ip, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(header.filename.as_deref(), Some("a.log"));
+        assert_eq!(header.path.as_deref(), Some("/var/a.log"));
+        assert_eq!(
+            (
+                header.file_offset,
+                header.file_line,
+                header.data_length,
+                header.block_index
+            ),
+            (Some(100), Some(7), Some(20), Some(3))
+        );
+        assert_eq!(header.topic.as_deref(), Some("raw"));
+        assert_eq!(header.version.as_deref(), Some("0.1.2"));
+        assert!(header.errors.is_empty());
+        // 带类型的字段不再留在 attr 中
+        assert!(!header.attr.contains_key("file_offset"));
+        assert_eq!(header.attr["custom"].as_str(), Some("x"));
+        assert_eq!(
+            header.source_file(),
+            Some(("host".to_string(), "/var/a.log".to_string()))
+        );
+        assert_eq!(header.get("file_offset").as_deref(), Some("100"));
+        assert_eq!(header.get("custom").as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn invalid_values_stay_in_attr() {
+        let header =
+            LogHeader::from_bytes(b"[[subsyscode=APP][ip=300.0.0.1][file_offset=abc][path=]]")
+                .unwrap();
+        assert_eq!(
+            (header.ip, header.file_offset, header.path),
+            (None, None, None)
+        );
+        assert_eq!(header.attr["ip"].as_str(), Some("300.0.0.1"));
+        assert_eq!(header.attr["file_offset"].as_str(), Some("abc"));
+        let keys: Vec<_> = header
+            .errors
+            .iter()
+            .map(|error| match error {
+                HeaderError::InvalidValue { key, .. } => key.as_str(),
+                HeaderError::MalformedPair { .. } => "",
+            })
+            .collect();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&"ip") && keys.contains(&"file_offset"));
+    }
+
+    #[test]
+    fn unknown_encoding_is_an_error() {
+        assert!(matches!(
+            LogHeader::from_bytes(b"[[subsyscode=APP][encode=EBCDIC-X]]"),
+            Err(ParseError::UnknownEncoding(label)) if label == "EBCDIC-X"
+        ));
+    }
+
+    #[test]
+    fn to_bytes_round_trips_typed_fields() {
+        let raw = b"[[subsyscode=APP][hostname=host][ip=::1][file_offset=100][custom=x]]";
+        let header = LogHeader::from_bytes(raw).unwrap();
+        let parsed = LogHeader::from_bytes(&header.to_bytes()).unwrap();
+        assert_eq!(parsed.subsys_code, "APP");
+        assert_eq!(parsed.hostname, header.hostname);
+        assert_eq!(parsed.ip, header.ip);
+        assert_eq!(parsed.file_offset, Some(100));
+        assert_eq!(parsed.attr["custom"].as_str(), Some("x"));
+
+        // 序列化时 attr 仍然包含全部键
+        let json = serde_json::to_value(&header).unwrap();
+        assert_eq!(json["file_offset"], 100);
+        assert_eq!(json["attr"]["file_offset"], "100");
+        assert_eq!(json["attr"]["custom"], "x");
+    }
+
+    #[test]
+    fn encoded_values_round_trip() {
+        let values = [
+            r"\[(\d+)\]",
+            "a]]b",
+            "k=v=w",
+            r"C:\logs\",
+            r"trailing\\",
+            r"(\r|\n)",
+            "[[nested]]",
+            "",
+            "中文",
+        ];
+        for value in values {
+            let header = encode_header([("subsyscode", "APP"), ("k[=]\\", value)]);
+            let raw = format!("{header}content");
+            let (header_bytes, content) = split_header(raw.as_bytes()).unwrap();
+            assert_eq!(content, b"content", "{value:?}");
+            let (pairs, errors) =
+                parse_header_kv(std::str::from_utf8(header_bytes).unwrap()).unwrap();
+            assert!(errors.is_empty(), "{value:?}: {errors:?}");
+            assert_eq!(pairs["k[=]\\"], value);
+        }
+    }
+
+    #[test]
+    fn unescaped_backslash_and_paired_brackets_are_kept() {
+        let (pairs, errors) =
+            parse_header_kv(r"[[pattern=(\r|\n)][re=[0-9]+][path=C:\a\b]]").unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(pairs["pattern"], r"(\r|\n)");
+        assert_eq!(pairs["re"], "[0-9]+");
+        assert_eq!(pairs["path"], r"C:\a\b");
+    }
+
+    #[test]
+    fn requires_leading_double_bracket() {
+        for raw in [
+            &b"[a=b]]content"[..],
+            b"a=b]]",
+            b"",
+            b"[[a=b]",
+            b"[[a=b][c=d",
+        ] {
+            assert!(
+                matches!(split_header(raw), Err(ParseError::MissingDelimiter)),
+                "{}",
+                String::from_utf8_lossy(raw)
+            );
+        }
+    }
+
+    #[test]
+    fn reports_malformed_pairs() {
+        let (pairs, errors) = parse_header_kv("[[encode-UTF-8][=x]stray[subsyscode=APP]]").unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs["subsyscode"], "APP");
+        let reasons: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                HeaderError::MalformedPair { offset, reason, .. } => (*offset, reason.as_str()),
+                HeaderError::InvalidValue { .. } => (0, ""),
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, "missing '='"),
+                (15, "empty key"),
+                (19, "unexpected text between pairs")
+            ]
+        );
+    }
+}
@@ -1,9 +1,28 @@
+pub mod compress;
 pub mod configuration;
+pub mod consumer;
+pub mod continuity;
 pub mod dao;
+pub mod datetime;
 pub mod db;
+pub mod dead_letter;
+pub mod dialect;
 pub mod dto;
+pub mod header;
+pub mod mask;
+pub mod metadata;
 pub mod models;
+pub mod reassemble;
+pub mod repository;
+pub mod resolver;
+pub mod rule_cache;
+pub mod rule_file;
 pub mod schema;
+pub mod sink;
+pub mod split;
+pub mod subsys;
 pub mod util;
+pub mod validate;
+pub mod value;
 
 pub mod error;
@@ -1,297 +1,513 @@
-use anyhow::anyhow;
-use chrono::prelude::*;
-use diesel::MysqlConnection;
-use diesel::sql_types::ops::Mul;
-use encoding_rs;
-use encoding_rs::Encoding;
-use env_logger;
-use log::{info, warn};
-use log_resolver_rs::dao::{
-    log_parser_field_dao, log_parser_pattern_dao, log_parser_rule_dao,
-    subsys_log_parser_config_dao, sys_subsys_config_dao,
-};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use std::any;
-use std::borrow::Cow;
-use std::collections::HashMap;
+use anyhow::{Context, anyhow};
+use clap::{Args, Parser, Subcommand};
+use log_resolver_rs::configuration::{Configuration, OutputConfig};
+use log_resolver_rs::consumer::{KafkaRecordSource, PartitionOffsets, Record};
+use log_resolver_rs::continuity::{Continuity, OffsetTracker};
+use log_resolver_rs::datetime::Zone;
+use log_resolver_rs::dead_letter::{self, DeadLetter};
+use log_resolver_rs::dialect::{HeaderDialects, SubsysSource};
+use log_resolver_rs::error::{ErrorKind, ParseError};
+use log_resolver_rs::header::encode_header;
+use log_resolver_rs::mask::Masker;
+use log_resolver_rs::metadata::KeyMapping;
+use log_resolver_rs::reassemble::Reassembler;
+use log_resolver_rs::repository::{self, MemoryRuleRepository, RuleRepository};
+use log_resolver_rs::resolver::{Resolution, Resolver};
+use log_resolver_rs::rule_cache::{RuleCache, RuleSet};
+use log_resolver_rs::rule_file;
+use log_resolver_rs::sink::{BatchedOutput, OutputMessage};
+use log_resolver_rs::subsys::SubsysChain;
+use log_resolver_rs::validate;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-fn main() -> anyhow::Result<()> {
-    env_logger::init();
-    dotenvy::dotenv().ok();
-
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let mut context = ApplicationContext::from(
-        <diesel::MysqlConnection as diesel::Connection>::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url)),
-    );
-
-    // loop {
-    let records: Vec<Record> = poll_records().unwrap();
-    log::info!("{}", records.len());
-    for record in records {
-        log::info!("{} {}", record.key, String::from_utf8_lossy(&record.value));
-        let logs = parse_log(&mut context, &record.value)?;
-        log::debug!("{logs:?}");
-    }
-    Ok(())
-}
-static MAIN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)^\[\[(.*?)\]\](.*)$").unwrap());
-static KV_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([^=\[\]]+)=([^\[\]]*)\]").unwrap());
-static DELIMITER: &'static [u8] = b"]]";
-fn parse_header_kv(header_content: &str) -> HashMap<String, String> {
-    let mut attributes = HashMap::new();
-    for kv_caps in KV_RE.captures_iter(header_content) {
-        if let (Some(key_match), Some(value_match)) = (kv_caps.get(1), kv_caps.get(2)) {
-            log::debug!(
-                "key: {}, value: {}",
-                key_match.as_str(),
-                value_match.as_str()
-            );
-            attributes.insert(
-                key_match.as_str().to_string(),
-                value_match.as_str().to_string(),
-            );
-        }
-    }
-    attributes
-}
-#[derive(Debug)]
-pub struct ParsedLog<'a> {
-    pub header: LogHeader,
-    pub log_content: Cow<'a, str>,
+#[derive(Parser)]
+#[command(version, about = "解析 Kafka 中的日志块并按规则输出")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug, Clone)]
-pub struct LogHeader {
-    pub subsys_code: String,
-    pub encode: &'static encoding_rs::Encoding,
-    pub attr: HashMap<String, String>,
+#[derive(Subcommand)]
+enum Command {
+    /// 消费 Kafka 中的日志并解析，不指定命令时的默认行为
+    Run,
+    /// 把数据库中的全部规则导出到 YAML/TOML/JSON 文件
+    Export {
+        /// 输出文件，格式由扩展名决定
+        file: PathBuf,
+        #[arg(long, env = "DATABASE_URL")]
+        database_url: String,
+    },
+    /// 用文件中的规则替换数据库中的全部规则
+    Import {
+        /// 规则文件，格式由扩展名决定
+        file: PathBuf,
+        #[arg(long, env = "DATABASE_URL")]
+        database_url: String,
+    },
+    /// 用规则解析样例输入，打印每个事件匹配的 pattern、捕获的字段、转换结果和错误
+    Test {
+        /// 输入文件，不指定或为 `-` 时读取标准输入
+        input: Option<PathBuf>,
+        /// 输入是不带头部的日志文本，按该子系统解析
+        #[arg(long)]
+        subsys: Option<String>,
+        /// 与 --subsys 一起使用，加入头部的键值对，例如 filename=app.log
+        #[arg(long = "header", value_name = "KEY=VALUE", requires = "subsys")]
+        headers: Vec<String>,
+        /// 每一行是一条记录，默认整个输入是一条记录
+        #[arg(long)]
+        per_line: bool,
+        #[command(flatten)]
+        resolver: ResolverArgs,
+    },
+    /// 用当前规则重新解析死信并输出，仍然失败的记录重新写入死信
+    ///
+    /// 使用 OUTPUT_SINK=file 时死信会追加到同一个文件，重放前先把它移走
+    Replay {
+        /// jsonl 格式的死信，`-` 为标准输入
+        input: PathBuf,
+        #[command(flatten)]
+        resolver: ResolverArgs,
+    },
+    /// 检查规则，有错误时返回非0
+    Validate {
+        #[command(flatten)]
+        source: RuleSourceArgs,
+        /// 有警告时也返回非0
+        #[arg(long)]
+        strict: bool,
+    },
 }
 
-#[derive(Debug)]
-pub struct Log<'a> {
-    pub date_time: DateTime<Local>,
-    pub log_header: LogHeader,
-    pub log_content: Cow<'a, str>,
+/// 规则的来源，指定 --rules 时使用规则文件，否则使用数据库
+#[derive(Args)]
+struct RuleSourceArgs {
+    /// YAML/TOML/JSON 规则文件
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: Option<String>,
 }
 
-impl LogHeader {
-    fn from_bytes(header_bytes: &[u8]) -> anyhow::Result<Self> {
-        let header_str = std::str::from_utf8(header_bytes)?; // 如果非 ASCII 会在此处报错
-        log::debug!("header: {:?}", header_str);
-        let headers = parse_header_kv(header_str);
-        let encoding_label_opt = headers.get("encode").map(|s| s.to_string());
-
-        let encoding_label = encoding_label_opt.as_deref().unwrap_or("UTF-8");
-
-        let encoding = get_encoding_from_label(encoding_label).unwrap();
-
-        let subsys_code = get_subsys_code(&headers).unwrap_or("null".to_string());
-        log::debug!("{subsys_code}");
+/// 离线解析使用的规则和选项
+#[derive(Args)]
+struct ResolverArgs {
+    #[command(flatten)]
+    source: RuleSourceArgs,
+    #[arg(long, env = "DATETIME_ZONE", default_value = "local")]
+    zone: String,
+    #[arg(long, env = "MASK_HMAC_KEY", hide_env_values = true)]
+    mask_hmac_key: Option<String>,
+    /// 没有头部的记录按 key 或 topic 取子系统，不指定时这类记录无法解析
+    #[arg(long, env = "HEADERLESS_SUBSYS_FROM", value_name = "key|topic")]
+    headerless_subsys_from: Option<String>,
+    /// `;` 分隔的子系统来源，例如 `fields0.SUBSYSCODE;regex:path:^/host/applogs/([^/]+)/`，regex 只能放在最后
+    #[arg(long, env = "SUBSYS_CODE_SOURCES", default_value = "")]
+    subsys_sources: String,
+    /// 找不到子系统时使用的子系统
+    #[arg(long, env = "SUBSYS_CODE_FALLBACK")]
+    subsys_fallback: Option<String>,
+}
 
-        Ok(LogHeader {
-            subsys_code,
-            encode: encoding,
-            attr: headers,
-        })
+impl ResolverArgs {
+    fn build(&self) -> anyhow::Result<Resolver> {
+        let rows = self.source.connect()?.load_all()?;
+        Ok(Resolver::from_rule_set(RuleSet::from_rows(rows))
+            .with_datetime_zone(Zone::parse(&self.zone)?)
+            .with_masker(Masker::new(
+                self.mask_hmac_key.clone().map(String::into_bytes),
+            ))
+            .with_key_mapping(KeyMapping::from_env()?)
+            .with_header_dialects(header_dialects(
+                self.headerless_subsys_from
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()?,
+            ))
+            .with_subsys_chain(SubsysChain::parse(
+                &self.subsys_sources,
+                self.subsys_fallback.as_deref(),
+            )?))
     }
 }
-fn parse_log<'a>(
-    context: &mut ApplicationContext,
-    raw_log: &'a Vec<u8>,
-) -> anyhow::Result<Vec<Log<'a>>> {
-    // 1: 找到头部和内容分隔符的位置
-    let conn = context.conn();
-    let delimiter_pos = raw_log
-        .windows(DELIMITER.len())
-        .position(|window| window == DELIMITER);
-    let (header_bytes, log_content_bytes) = match delimiter_pos {
-        Some(pos) => {
-            let header = &raw_log[..pos + DELIMITER.len()];
-            let content = &raw_log[pos + DELIMITER.len()..];
-            (header, content)
-        }
-        None => return Err(anyhow!("log header delimiter not found")),
-    };
-
-    // 2: 解析头部
-    let log_header = LogHeader::from_bytes(header_bytes)?;
 
-    // 3: 解码日志字符串
-    let (decoded_log_cow, actual_encoding, had_errors) =
-        log_header.encode.decode(log_content_bytes);
-    log::debug!(
-        "Decoded log: {:?}, encoding: {:?}, had_errors: {:?}",
-        decoded_log_cow,
-        actual_encoding,
-        had_errors
-    );
-    if had_errors {
-        log::warn!(
-            "Error while decoding log content from {}",
-            log_header.subsys_code
-        );
+impl RuleSourceArgs {
+    fn connect(&self) -> anyhow::Result<Box<dyn RuleRepository>> {
+        match (&self.rules, &self.database_url) {
+            (Some(rules), _) => Ok(Box::new(MemoryRuleRepository::from_file(rules)?)),
+            (None, Some(database_url)) => repository::connect(database_url),
+            (None, None) => Err(anyhow!("either --rules or DATABASE_URL must be set")),
+        }
     }
-
-    let sys_subsys_config =
-        sys_subsys_config_dao::query_by_subsys_code(conn, &log_header.subsys_code).unwrap();
-    let subsys_log_parser_config_list =
-        subsys_log_parser_config_dao::query_by_subsys_code(conn, &log_header.subsys_code);
-
-    let logs: anyhow::Result<Vec<Log<'_>>> = subsys_log_parser_config_list.into_iter().try_fold(
-        vec![],
-        |mut v, subsys_log_parser_config| {
-            let logs = apply_parse_config(
-                conn,
-                &log_header,
-                &decoded_log_cow,
-                subsys_log_parser_config,
-            )?;
-            v.extend(logs);
-            Ok(v)
-        },
-    );
-
-    logs
 }
 
-fn apply_parse_config<'a>(
-    conn: &mut MysqlConnection,
-    log_header: &LogHeader,
-    decoded_log_cow: &Cow<'a, str>,
-    subsys_log_parser_config: log_resolver_rs::models::SubsysLogParser,
-) -> anyhow::Result<Vec<Log<'a>>> {
-    let parser_rule_id = subsys_log_parser_config.log_parser_rule_id;
-    // let log_parser_rule = log_parser_rule_dao::query_by_id(conn, parser_rule_id).unwrap();
-    let log_parser_pattern_list =
-        log_parser_pattern_dao::query_by_log_parser_rule_id(conn, parser_rule_id);
-    // 每个pattern都产生一个Log
-    let logs: Vec<Log<'_>> = log_parser_pattern_list
-        .into_iter()
-        .filter_map(|log_parser_pattern| {
-            let pattern =
-                regex::Regex::new(log_parser_pattern.pattern.unwrap_or_default().as_str()).ok()?;
-
-            pattern.captures(decoded_log_cow).map(|captures| {
-                let mut log = Log {
-                    date_time: Local::now(),
-                    log_header: log_header.clone(),
-                    log_content: decoded_log_cow.clone(),
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    dotenvy::dotenv().ok();
+    match Cli::parse().command.unwrap_or(Command::Run) {
+        Command::Run => run(),
+        Command::Export { file, database_url } => {
+            let rows = repository::connect(&database_url)?.dump()?;
+            rule_file::write(&file, &rows)?;
+            log::info!("exported rules to {}", file.display());
+            Ok(())
+        }
+        Command::Import { file, database_url } => {
+            let rows = rule_file::read(&file)?;
+            repository::connect(&database_url)?.replace_all(&rows)?;
+            log::info!("imported rules from {}", file.display());
+            Ok(())
+        }
+        Command::Test {
+            input,
+            subsys,
+            headers,
+            per_line,
+            resolver,
+        } => {
+            let resolver = resolver.build()?;
+            let input = read_input(input.as_deref())?;
+            let header = match subsys {
+                Some(subsys) => Some(sample_header(&subsys, &headers)?),
+                None => None,
+            };
+            let records: Vec<&[u8]> = if per_line {
+                input
+                    .split(|&b| b == b'\n')
+                    .filter(|line| !line.trim_ascii().is_empty())
+                    .collect()
+            } else {
+                vec![&input]
+            };
+            let mut failed = 0;
+            for (index, record) in records.into_iter().enumerate() {
+                println!("record {}", index + 1);
+                let raw = match &header {
+                    Some(header) => [header.as_bytes(), record].concat(),
+                    None => record.to_vec(),
                 };
-                // let mut attr = log.log_header.attr;
-
-                pattern.capture_names().flatten().for_each(|group_name| {
-                    let group_value = captures.name(group_name).unwrap();
-                    if let Some(log_parser_field) =
-                        log_parser_field_dao::query_by_log_parser_rule_id_and_name_in_capture(
-                            conn,
-                            parser_rule_id,
-                            group_name,
-                        )
-                    {
-                        match log_parser_field.type_ {
-                            10 => {
-                                chrono::DateTime::parse_from_str(
-                                    group_value.as_str(),
-                                    log_parser_field.format_pattern.unwrap().as_str(),
-                                )
-                                .map(|dt| log.date_time = dt.into())
-                                .ok();
-                            }
-                            0 => {
-                                log.log_header.attr.insert(
-                                    group_name.to_string(),
-                                    group_value.as_str().to_string(),
-                                );
-                            }
-                            _ => log::warn!("unsupported group type"),
-                        }
-                    } else {
-                        log.log_header
-                            .attr
-                            .insert(group_name.to_string(), group_value.as_str().to_string());
+                if let Err(error) = print_trace(&resolver, &raw) {
+                    match error.downcast_ref::<ParseError>() {
+                        Some(parse_error) => println!("  error: {}: {error:#}", parse_error.kind()),
+                        None => println!("  error: {error:#}"),
                     }
-                });
-
-                log
-            })
-        })
-        .collect();
-    log::info!("{:?}", subsys_log_parser_config);
-    Ok(logs)
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{failed} record(s) failed"));
+            }
+            Ok(())
+        }
+        Command::Replay { input, resolver } => {
+            let resolver = resolver.build()?;
+            let config = OutputConfig::from_env()?;
+            let mut output = BatchedOutput::from_config(&config)?;
+            let dead_letters = dead_letter::read_jsonl(&input)?;
+            let mut failed = 0;
+            let mut skipped = 0;
+            for dead_letter in &dead_letters {
+                let Some(record) = dead_letter.record() else {
+                    log::warn!(
+                        "dead letter {}:{}@{} has no raw record, set DEAD_LETTER_INCLUDE_RAW to replay",
+                        dead_letter.topic,
+                        dead_letter.partition,
+                        dead_letter.offset
+                    );
+                    skipped += 1;
+                    continue;
+                };
+                if !handle_record(
+                    &resolver,
+                    &mut output,
+                    &config,
+                    &record,
+                    &dead_letter.line_offsets,
+                )? {
+                    failed += 1;
+                }
+            }
+            output.flush()?;
+            log::info!(
+                "replayed {} dead letter(s), {failed} still failing, {skipped} without raw record",
+                dead_letters.len() - skipped
+            );
+            Ok(())
+        }
+        Command::Validate { source, strict } => {
+            let rows = source.connect()?.load_all()?;
+            let issues = validate::validate(&rows);
+            for issue in &issues {
+                println!("{issue}");
+            }
+            let errors = validate::failures(&issues, false);
+            let warnings = issues.len() - errors;
+            println!("{errors} error(s), {warnings} warning(s)");
+            if validate::failures(&issues, strict) > 0 {
+                return Err(anyhow!("rule validation failed"));
+            }
+            Ok(())
+        }
+    }
 }
 
-fn get_subsys_code(headers: &HashMap<String, String>) -> Option<String> {
-    headers
-        .get("fields0.SUBSYSCODE")
-        .or_else(|| headers.get("subsyscode"))
-        .cloned()
+fn read_input(input: Option<&Path>) -> anyhow::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    match input.filter(|p| *p != Path::new("-")) {
+        Some(path) => {
+            content =
+                fs::read(path).with_context(|| format!("failed to read {}", path.display()))?
+        }
+        None => {
+            io::stdin().read_to_end(&mut content)?;
+        }
+    }
+    Ok(content)
 }
 
-// 预定义常见的编码标签映射 (可选，提高查找效率)
-static ENCODING_MAP: Lazy<HashMap<&'static str, &'static Encoding>> = Lazy::new(|| {
-    let mut m = HashMap::new();
-    m.insert("utf-8", encoding_rs::UTF_8);
-    m.insert("utf8", encoding_rs::UTF_8);
-    m.insert("gbk", encoding_rs::GBK);
-    m.insert("gb18030", encoding_rs::GB18030);
-    // 添加其他你可能需要支持的编码
-    m.insert("latin1", encoding_rs::WINDOWS_1252); // alias for latin1
-    m.insert("windows-1252", encoding_rs::WINDOWS_1252);
-    m
-});
+fn header_dialects(headerless_subsys_from: Option<SubsysSource>) -> HeaderDialects {
+    match headerless_subsys_from {
+        Some(subsys_from) => HeaderDialects::default().with_headerless(subsys_from),
+        None => HeaderDialects::default(),
+    }
+}
 
-// 查找编码的辅助函数
-fn get_encoding_from_label(label: &str) -> Option<&'static Encoding> {
-    // 优先使用预定义 Map (更快)
-    if let Some(encoding) = ENCODING_MAP.get(label.to_lowercase().as_str()) {
-        return Some(encoding);
+/// `--subsys` 和 `--header` 组成的头部
+fn sample_header(subsys: &str, headers: &[String]) -> anyhow::Result<String> {
+    let mut pairs = vec![("subsyscode", subsys)];
+    for kv in headers {
+        let Some(pair) = kv.split_once('=') else {
+            return Err(anyhow!("invalid header {kv}, expected KEY=VALUE"));
+        };
+        pairs.push(pair);
     }
-    // 其次尝试 encoding_rs 的动态查找
-    Encoding::for_label(label.as_bytes())
+    Ok(encode_header(pairs))
 }
 
-fn poll_records() -> anyhow::Result<Vec<Record>> {
-    let records = vec![
-        Record {
-            key: "".to_string(),
-            value: Vec::from(
-                "[[subsyscode=SUBSYS_TEST][encode=utf-8]]2025-01-01 22:22:22.222 |INFO| MSG",
-            ),
-            timestamp: 1000000,
-        },
-        // Record {
-        //     key: "".to_string(),
-        //     value: Vec::from(""),
-        //     timestamp: 1000001,
-        // },
-        // Record {
-        //     key: "".to_string(),
-        //     value: Vec::from(""),
-        //     timestamp: 1000002,
-        // },
-    ];
-    Ok(records)
+fn print_trace(resolver: &Resolver, raw: &[u8]) -> anyhow::Result<()> {
+    let (resolution, traces) = resolver.trace(raw)?;
+    if traces.is_empty() {
+        println!("  no subsys_log_parser matches this record");
+    }
+    let mut logs = resolution.logs.iter();
+    for trace in &traces {
+        print!(
+            "  event at line {} (subsys_log_parser {}): ",
+            trace.line_offset, trace.subsys_log_parser_id
+        );
+        let Some(pattern_id) = trace.pattern_id else {
+            println!("no pattern matched");
+            for line in trace.content.lines() {
+                println!("    | {line}");
+            }
+            continue;
+        };
+        println!("log_parser_pattern {pattern_id}");
+        for (name, value) in &trace.captures {
+            match value {
+                Some(value) => println!("    {name} = {value:?}"),
+                None => println!("    {name} (not matched)"),
+            }
+        }
+        if let Some(log) = logs.next() {
+            println!("    => {}", serde_json::to_string(log)?);
+            for error in &log.errors {
+                println!("    ! {}: {error}", error.kind());
+            }
+        }
+    }
+    Ok(())
 }
 
-struct Record {
-    key: String,
-    value: Vec<u8>,
-    timestamp: u64,
+fn run() -> anyhow::Result<()> {
+    let configuration = Configuration::from_env()?;
+
+    let mut repository = repository::connect(&configuration.database_url)?;
+    let rules = Arc::new(RuleCache::load(
+        repository.as_mut(),
+        configuration.rules_strict,
+    )?);
+    let resolver = Resolver::new(rules.clone())
+        .with_datetime_zone(configuration.datetime_zone)
+        .with_masker(Masker::new(configuration.mask_hmac_key.clone()))
+        .with_max_decompressed_size(configuration.max_decompressed_size)
+        .with_key_mapping(configuration.key_mapping.clone())
+        .with_header_dialects(header_dialects(configuration.headerless_subsys_from))
+        .with_subsys_chain(configuration.subsys_chain.clone());
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || {
+            log::info!("received shutdown signal");
+            shutdown.store(true, Ordering::Relaxed);
+        })?;
+    }
+    let refresher = rules.spawn_refresher(
+        configuration.database_url.clone(),
+        configuration.rule_refresh_interval,
+        shutdown.clone(),
+    );
+
+    let mut output = BatchedOutput::from_config(&configuration.output)?;
+    let source = KafkaRecordSource::new(&configuration.consumer)?;
+    let continuity_topic = configuration.output.continuity_topic.as_deref();
+    let mut reassembler = configuration.reassembly_timeout.map(Reassembler::new);
+    let mut tracker = OffsetTracker::new();
+    let mut stats_logged_at = Instant::now();
+    let mut handled = PartitionOffsets::default();
+    let mut flushed = output.flushed();
+    let result = source.run_with_idle(&shutdown, |record| {
+        if let Some(record) = record {
+            log::debug!("{} {}", record.key, String::from_utf8_lossy(&record.value));
+            // 头部无法解析的记录由解析时输出到死信
+            let event = match resolver.decode_record_header(record) {
+                Ok(header) => tracker.observe(&header, record).unwrap_or_else(|error| {
+                    log::warn!("{error} from {}", header.subsys_code);
+                    None
+                }),
+                Err(_) => None,
+            };
+            if let Some(event) = &event {
+                log::warn!(
+                    "{:?} in {}:{}, expected offset {}, got {}",
+                    event.kind,
+                    event.hostname,
+                    event.path,
+                    event.expected_offset,
+                    event.file_offset
+                );
+                if let Some(topic) = continuity_topic {
+                    output.push(event.to_message(topic)?)?;
+                }
+            }
+            let duplicate = event.is_some_and(|e| e.kind == Continuity::Duplicate);
+            match reassembler.as_mut() {
+                // 重复的日志块已经处理过
+                _ if duplicate => {}
+                Some(reassembler) => {
+                    for reassembled in reassembler.push(&resolver, record) {
+                        emit(
+                            &mut output,
+                            &configuration.output,
+                            &reassembled.record,
+                            reassembled.result,
+                        )?;
+                    }
+                }
+                None => {
+                    handle_record(&resolver, &mut output, &configuration.output, record, &[])?;
+                }
+            }
+            handled.handled(record);
+        }
+        if let Some(reassembler) = reassembler.as_mut() {
+            for reassembled in reassembler.flush_expired(&resolver) {
+                emit(
+                    &mut output,
+                    &configuration.output,
+                    &reassembled.record,
+                    reassembled.result,
+                )?;
+            }
+        }
+        if stats_logged_at.elapsed() >= configuration.stats_interval {
+            log::info!("{:?}", tracker.stats());
+            stats_logged_at = Instant::now();
+        }
+        let finishing = shutdown.load(Ordering::Relaxed);
+        if finishing && let Some(reassembler) = reassembler.as_mut() {
+            for reassembled in reassembler.flush_all(&resolver) {
+                emit(
+                    &mut output,
+                    &configuration.output,
+                    &reassembled.record,
+                    reassembled.result,
+                )?;
+            }
+        }
+        // 攒满一批发送后、空闲时和退出前提交，提交前先把缓冲区剩下的消息送达
+        if record.is_some() && !finishing && output.flushed() == flushed {
+            return Ok(None);
+        }
+        output.flush()?;
+        flushed = output.flushed();
+        // 未完成的事件所在的记录还需要重新消费
+        match reassembler.as_ref() {
+            Some(reassembler) => Ok(Some(handled.capped(&reassembler.pending_offsets()))),
+            None => Ok(Some(handled.clone())),
+        }
+    });
+    log::info!("{:?}", tracker.stats());
+    refresher.join().ok();
+    result
 }
 
-pub struct ApplicationContext {
-    // 数据库连接
-    conn: diesel::MysqlConnection,
+/// 输出一条记录的解析结果，解析失败和没有匹配的事件写入死信
+///
+/// `line_offsets` 不为空时只输出这些行上的事件。记录完全解析成功时返回 true
+fn handle_record(
+    resolver: &Resolver,
+    output: &mut BatchedOutput,
+    config: &OutputConfig,
+    record: &Record,
+    line_offsets: &[usize],
+) -> anyhow::Result<bool> {
+    let result = resolver.resolve_record(record).map(|mut resolution| {
+        if !line_offsets.is_empty() {
+            resolution.retain_line_offsets(line_offsets);
+        }
+        resolution
+    });
+    emit(output, config, record, result)
 }
 
-impl ApplicationContext {
-    pub fn conn(&mut self) -> &mut MysqlConnection {
-        &mut self.conn
+/// 输出解析结果，`record` 是实际解析的记录
+fn emit(
+    output: &mut BatchedOutput,
+    config: &OutputConfig,
+    record: &Record,
+    result: Result<Resolution, ParseError>,
+) -> anyhow::Result<bool> {
+    let dead_letter = match result {
+        Ok(resolution) => {
+            log::debug!("{resolution:?}");
+            for log in &resolution.logs {
+                output.push(OutputMessage {
+                    topic: log.source_topic.clone(),
+                    key: Some(record.key.clone()).filter(|k| !k.is_empty()),
+                    payload: serde_json::to_vec(log)?,
+                })?;
+            }
+            DeadLetter::unmatched(record, &resolution)
+        }
+        // 规则存储不可用不是记录的问题，停止消费，没有提交的记录重启后重新解析
+        Err(error) if error.kind() == ErrorKind::RepositoryUnavailable => return Err(error.into()),
+        Err(error) => Some(DeadLetter::failed(record, &error)),
+    };
+    let Some(mut dead_letter) = dead_letter else {
+        return Ok(true);
+    };
+    if !config.dead_letter_include_raw {
+        dead_letter = dead_letter.without_raw();
     }
-
-    pub fn from(conn: MysqlConnection) -> Self {
-        Self { conn }
+    log::warn!(
+        "dead letter {}:{}@{} ({}): {}",
+        record.topic,
+        record.partition,
+        record.offset,
+        dead_letter.error_kind,
+        dead_letter.error
+    );
+    match config.dead_letter_topic.as_deref() {
+        Some(topic) => output.push(dead_letter.to_message(topic)?)?,
+        None => log::warn!("DEAD_LETTER_TOPIC is empty, dropping the record"),
     }
+    Ok(false)
 }
@@ -0,0 +1,227 @@
+use std::sync::Once;
+
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::models::LogParserField;
+
+/// 完全脱敏时输出的内容
+pub const REDACTED: &str = "******";
+
+/// 没有配置 MASK_HMAC_KEY 的警告只输出一次
+static MISSING_HMAC_KEY: Once = Once::new();
+
+/// 敏感字段(log_parser_field.is_sensitive)的脱敏方式，来自 log_parser_field.mask_strategy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaskStrategy {
+    /// `redact`，整个替换成 `******`，未配置时的默认值
+    Redact,
+    /// `partial:N`，只保留最后N个字符，其余替换成 `*`
+    Partial { keep_last: usize },
+    /// `hmac`，替换成带密钥的 HMAC-SHA256，相同的值得到相同的结果，可以用于关联
+    Hmac,
+    /// `drop`，不输出这个字段
+    Drop,
+}
+
+impl MaskStrategy {
+    /// 字段不敏感时返回 None
+    pub fn from_field(field: &LogParserField) -> anyhow::Result<Option<Self>> {
+        if field.is_sensitive != Some(true) {
+            return Ok(None);
+        }
+        let strategy = field.mask_strategy.as_deref().unwrap_or_default().trim();
+        let (name, arg) = match strategy.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (strategy, None),
+        };
+        let strategy = match (name.to_ascii_lowercase().as_str(), arg) {
+            ("" | "redact", None) => MaskStrategy::Redact,
+            ("partial", None) => MaskStrategy::Partial { keep_last: 4 },
+            ("partial", Some(n)) => MaskStrategy::Partial {
+                keep_last: n
+                    .parse()
+                    .map_err(|_| anyhow!("invalid partial mask length {n:?}"))?,
+            },
+            ("hmac", None) => MaskStrategy::Hmac,
+            ("drop", None) => MaskStrategy::Drop,
+            _ => return Err(anyhow!("unknown mask strategy {strategy:?}")),
+        };
+        Ok(Some(strategy))
+    }
+}
+
+/// 执行脱敏，持有 HMAC 的密钥
+#[derive(Clone, Default)]
+pub struct Masker {
+    hmac_key: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for Masker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Masker")
+            .field("hmac_key", &self.hmac_key.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+impl Masker {
+    pub fn new(hmac_key: Option<Vec<u8>>) -> Self {
+        Self {
+            hmac_key: hmac_key.filter(|k| !k.is_empty()),
+        }
+    }
+
+    /// 返回脱敏后的值，None 表示丢弃这个字段
+    ///
+    /// 没有配置密钥时 hmac 退化为完全脱敏，保证敏感值不会原样输出
+    pub fn mask(&self, strategy: &MaskStrategy, value: &str) -> Option<String> {
+        match strategy {
+            MaskStrategy::Redact => Some(REDACTED.to_string()),
+            MaskStrategy::Partial { keep_last } => {
+                let len = value.chars().count();
+                let hidden = len.saturating_sub(*keep_last);
+                Some(
+                    value
+                        .chars()
+                        .enumerate()
+                        .map(|(i, c)| if i < hidden { '*' } else { c })
+                        .collect(),
+                )
+            }
+            MaskStrategy::Hmac => match &self.hmac_key {
+                Some(key) => {
+                    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+                        .expect("HMAC accepts keys of any length");
+                    mac.update(value.as_bytes());
+                    Some(
+                        mac.finalize()
+                            .into_bytes()
+                            .iter()
+                            .map(|b| format!("{b:02x}"))
+                            .collect(),
+                    )
+                }
+                None => {
+                    MISSING_HMAC_KEY.call_once(|| {
+                        log::warn!("MASK_HMAC_KEY is not set, falling back to redaction")
+                    });
+                    Some(REDACTED.to_string())
+                }
+            },
+            MaskStrategy::Drop => None,
+        }
+    }
+
+    /// 日志原文中对应位置的替换内容，字段被丢弃时原文中也完全脱敏
+    pub fn mask_in_content(&self, strategy: &MaskStrategy, value: &str) -> String {
+        self.mask(strategy, value)
+            .unwrap_or_else(|| REDACTED.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(is_sensitive: Option<bool>, mask_strategy: Option<&str>) -> LogParserField {
+        LogParserField {
+            id: 1,
+            log_parser_rule_id: 1,
+            name: None,
+            name_in_capture: "card".to_string(),
+            type_: 0,
+            format_pattern: None,
+            default_val: None,
+            is_sensitive,
+            mask_strategy: mask_strategy.map(str::to_string),
+        }
+    }
+
+    fn strategy(mask_strategy: Option<&str>) -> anyhow::Result<Option<MaskStrategy>> {
+        MaskStrategy::from_field(&field(Some(true), mask_strategy))
+    }
+
+    #[test]
+    fn parses_mask_strategies() {
+        assert_eq!(
+            MaskStrategy::from_field(&field(None, Some("hmac"))).unwrap(),
+            None
+        );
+        assert_eq!(
+            MaskStrategy::from_field(&field(Some(false), Some("drop"))).unwrap(),
+            None
+        );
+        assert_eq!(strategy(None).unwrap(), Some(MaskStrategy::Redact));
+        assert_eq!(
+            strategy(Some(" Redact ")).unwrap(),
+            Some(MaskStrategy::Redact)
+        );
+        assert_eq!(
+            strategy(Some("partial")).unwrap(),
+            Some(MaskStrategy::Partial { keep_last: 4 })
+        );
+        assert_eq!(
+            strategy(Some("partial: 2")).unwrap(),
+            Some(MaskStrategy::Partial { keep_last: 2 })
+        );
+        assert_eq!(strategy(Some("HMAC")).unwrap(), Some(MaskStrategy::Hmac));
+        assert_eq!(strategy(Some("drop")).unwrap(), Some(MaskStrategy::Drop));
+        assert!(strategy(Some("partial:x")).is_err());
+        assert!(strategy(Some("hmac:1")).is_err());
+        assert!(strategy(Some("hash")).is_err());
+    }
+
+    #[test]
+    fn masks_values() {
+        let masker = Masker::default();
+        assert_eq!(
+            masker.mask(&MaskStrategy::Redact, "secret").as_deref(),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            masker
+                .mask(&MaskStrategy::Partial { keep_last: 4 }, "6222021234")
+                .as_deref(),
+            Some("******1234")
+        );
+        // 按字符而不是字节保留
+        assert_eq!(
+            masker
+                .mask(&MaskStrategy::Partial { keep_last: 1 }, "张三丰")
+                .as_deref(),
+            Some("**丰")
+        );
+        assert_eq!(
+            masker
+                .mask(&MaskStrategy::Partial { keep_last: 8 }, "abc")
+                .as_deref(),
+            Some("abc")
+        );
+        assert_eq!(masker.mask(&MaskStrategy::Drop, "secret"), None);
+        assert_eq!(
+            masker.mask_in_content(&MaskStrategy::Drop, "secret"),
+            REDACTED
+        );
+    }
+
+    #[test]
+    fn hmac_is_keyed_and_stable() {
+        let a = Masker::new(Some(b"key-a".to_vec()));
+        let b = Masker::new(Some(b"key-b".to_vec()));
+        let hash = a.mask(&MaskStrategy::Hmac, "secret").unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(a.mask(&MaskStrategy::Hmac, "secret").unwrap(), hash);
+        assert_ne!(a.mask(&MaskStrategy::Hmac, "other").unwrap(), hash);
+        assert_ne!(b.mask(&MaskStrategy::Hmac, "secret").unwrap(), hash);
+        // 没有密钥时不会原样输出
+        assert_eq!(
+            Masker::new(Some(Vec::new()))
+                .mask(&MaskStrategy::Hmac, "secret")
+                .as_deref(),
+            Some(REDACTED)
+        );
+        assert!(!format!("{a:?}").contains("key-a"));
+    }
+}
@@ -0,0 +1,254 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::{Map, Value as Json};
+
+use crate::header::LogHeader;
+
+/// 嵌套后的键名的大小写
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyCase {
+    /// 转为小写，例如 `fields0.CLUSTERNAME` 输出为 `clustername`
+    #[default]
+    Lower,
+    /// 保持头部中的写法
+    Preserve,
+}
+
+impl FromStr for KeyCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "lower" => Ok(KeyCase::Lower),
+            "preserve" => Ok(KeyCase::Preserve),
+            _ => Err(anyhow!("unknown key case {s}, expected lower or preserve")),
+        }
+    }
+}
+
+/// `kubernetes` 中的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KubernetesField {
+    Pod,
+    Namespace,
+    Node,
+    Container,
+    Uid,
+}
+
+impl FromStr for KubernetesField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pod" => Ok(KubernetesField::Pod),
+            "namespace" => Ok(KubernetesField::Namespace),
+            "node" => Ok(KubernetesField::Node),
+            "container" => Ok(KubernetesField::Container),
+            "uid" => Ok(KubernetesField::Uid),
+            _ => Err(anyhow!(
+                "unknown kubernetes field {s}, expected pod, namespace, node, container or uid"
+            )),
+        }
+    }
+}
+
+/// 采集端写入的 kubernetes 元数据，每个 Log 都输出，没有的字段为 null
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Kubernetes {
+    pub pod: Option<String>,
+    pub namespace: Option<String>,
+    pub node: Option<String>,
+    pub container: Option<String>,
+    pub uid: Option<String>,
+}
+
+impl Kubernetes {
+    fn field_mut(&mut self, field: KubernetesField) -> &mut Option<String> {
+        match field {
+            KubernetesField::Pod => &mut self.pod,
+            KubernetesField::Namespace => &mut self.namespace,
+            KubernetesField::Node => &mut self.node,
+            KubernetesField::Container => &mut self.container,
+            KubernetesField::Uid => &mut self.uid,
+        }
+    }
+}
+
+/// 头部中 `fields0.*` 这类扁平的键到结构化元数据的映射
+///
+/// attr 保持不变，映射的结果写入头部的 metadata 和 kubernetes
+#[derive(Debug, Clone)]
+pub struct KeyMapping {
+    /// 以这些前缀开头的键按 `.` 拆分为嵌套对象，前缀不区分大小写
+    pub nested_prefixes: Vec<String>,
+    pub key_case: KeyCase,
+    /// 头部键名到 kubernetes 字段，键名不区分大小写，同一字段可以有多个键，取第一个有值的
+    pub kubernetes: Vec<(String, KubernetesField)>,
+}
+
+impl Default for KeyMapping {
+    fn default() -> Self {
+        Self {
+            nested_prefixes: vec!["fields0".to_string()],
+            key_case: KeyCase::default(),
+            kubernetes: [
+                ("fields0.k8s_pod", KubernetesField::Pod),
+                ("fields0.k8s_pod_namespace", KubernetesField::Namespace),
+                ("fields0.k8s_node_name", KubernetesField::Node),
+                ("fields0.k8s_container_name", KubernetesField::Container),
+                ("fields0.k8s_pod_uid", KubernetesField::Uid),
+            ]
+            .into_iter()
+            .map(|(key, field)| (key.to_string(), field))
+            .collect(),
+        }
+    }
+}
+
+impl KeyMapping {
+    pub fn apply(&self, header: &mut LogHeader) {
+        let mut attr: Vec<_> = header.attr.iter().collect();
+        attr.sort_by_key(|(k, _)| k.as_str());
+        for (key, value) in attr {
+            let Some(value) = value.as_str().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            for (_, field) in self
+                .kubernetes
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            {
+                header
+                    .kubernetes
+                    .field_mut(*field)
+                    .get_or_insert_with(|| value.to_string());
+            }
+            if self.is_nested(key) {
+                let path: Vec<_> = key.split('.').map(|s| self.normalize(s)).collect();
+                if !insert_nested(&mut header.metadata, &path, value) {
+                    log::debug!("header key {key} conflicts with another nested key");
+                }
+            }
+        }
+    }
+
+    fn is_nested(&self, key: &str) -> bool {
+        key.split_once('.').is_some_and(|(prefix, _)| {
+            self.nested_prefixes
+                .iter()
+                .any(|p| p.eq_ignore_ascii_case(prefix))
+        })
+    }
+
+    fn normalize(&self, segment: &str) -> String {
+        match self.key_case {
+            KeyCase::Lower => segment.to_lowercase(),
+            KeyCase::Preserve => segment.to_string(),
+        }
+    }
+}
+
+/// 按 `path` 放入嵌套的对象，路径上已经有非对象的值或者同名的键时返回 false
+fn insert_nested(map: &mut Map<String, Json>, path: &[String], value: &str) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut map = map;
+    for segment in parents {
+        let entry = map
+            .entry(segment.clone())
+            .or_insert_with(|| Json::Object(Map::new()));
+        let Json::Object(child) = entry else {
+            return false;
+        };
+        map = child;
+    }
+    if map.contains_key(last) {
+        return false;
+    }
+    map.insert(last.clone(), Json::String(value.to_string()));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn header(raw: &[u8]) -> LogHeader {
+        LogHeader::from_bytes(raw).unwrap()
+    }
+
+    const RAW: &[u8] = b"[[subsyscode=APP][fields0.k8s_pod=pod-1][fields0.k8s_pod_namespace=ns]\
+[fields0.CLUSTERNAME=c1][fields0.labels.app=web][other.key=x]]";
+
+    #[test]
+    fn nests_prefixed_keys_in_lower_case() {
+        let mut header = header(RAW);
+        KeyMapping::default().apply(&mut header);
+        assert_eq!(
+            Json::Object(header.metadata.clone()),
+            json!({"fields0": {
+                "k8s_pod": "pod-1",
+                "k8s_pod_namespace": "ns",
+                "clustername": "c1",
+                "labels": {"app": "web"},
+            }})
+        );
+        assert_eq!(
+            header.kubernetes,
+            Kubernetes {
+                pod: Some("pod-1".to_string()),
+                namespace: Some("ns".to_string()),
+                ..Kubernetes::default()
+            }
+        );
+        // attr 保持不变
+        assert_eq!(header.attr["fields0.CLUSTERNAME"].as_str(), Some("c1"));
+        assert_eq!(header.get("kubernetes.pod").as_deref(), Some("pod-1"));
+    }
+
+    #[test]
+    fn preserves_key_case_and_custom_prefixes() {
+        let mut header = header(RAW);
+        let key_mapping = KeyMapping {
+            nested_prefixes: vec!["OTHER".to_string()],
+            key_case: KeyCase::Preserve,
+            kubernetes: vec![("FIELDS0.CLUSTERNAME".to_string(), KubernetesField::Node)],
+        };
+        key_mapping.apply(&mut header);
+        assert_eq!(
+            Json::Object(header.metadata.clone()),
+            json!({"other": {"key": "x"}})
+        );
+        assert_eq!(header.kubernetes.node.as_deref(), Some("c1"));
+        assert_eq!(header.kubernetes.pod, None);
+    }
+
+    #[test]
+    fn conflicting_keys_keep_the_first_in_key_order() {
+        let mut header = header(b"[[subsyscode=APP][fields0.a=1][fields0.A.b=2]]");
+        KeyMapping::default().apply(&mut header);
+        // 按键名排序后 `fields0.A.b` 在前，`fields0.a` 与它冲突被跳过
+        assert_eq!(
+            Json::Object(header.metadata.clone()),
+            json!({"fields0": {"a": {"b": "2"}}})
+        );
+    }
+
+    #[test]
+    fn parses_key_case_and_kubernetes_fields() {
+        assert_eq!(" Lower ".parse::<KeyCase>().unwrap(), KeyCase::Lower);
+        assert_eq!("PRESERVE".parse::<KeyCase>().unwrap(), KeyCase::Preserve);
+        assert!("upper".parse::<KeyCase>().is_err());
+        assert_eq!(
+            "Namespace".parse::<KubernetesField>().unwrap(),
+            KubernetesField::Namespace
+        );
+        assert!("cluster".parse::<KubernetesField>().is_err());
+    }
+}
@@ -2,7 +2,7 @@ use crate::schema;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = schema::sys_subsys_config)]
 // 显式检查 MySQL 后端有助于捕获类型不匹配
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -16,7 +16,7 @@ pub struct SysSubsysConfig {
     pub subsys_name: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = schema::subsys_log_parser)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct SubsysLogParser {
@@ -29,9 +29,11 @@ pub struct SubsysLogParser {
     pub status: bool,
     pub log_split: Option<String>,
     pub source_topic: String,
+    #[serde(default)]
+    pub use_header_pattern: bool,
 }
 
-#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = schema::log_parser_rule)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct LogParserRule {
@@ -42,7 +44,7 @@ pub struct LogParserRule {
     pub chinese_name: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = schema::log_parser_pattern)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct LogParserPattern {
@@ -54,7 +56,7 @@ pub struct LogParserPattern {
     pub pattern: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = schema::log_parser_field)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct LogParserField {
@@ -65,8 +67,10 @@ pub struct LogParserField {
     pub name: Option<String>,
     pub name_in_capture: String,
     // 'type' is a Rust keyword, rename it and map using column_name
+    #[serde(rename = "type")]
     pub type_: i32, // Mapped from INT NOT NULL
     pub format_pattern: Option<String>,
     pub default_val: Option<String>,
     pub is_sensitive: Option<bool>,
+    pub mask_strategy: Option<String>,
 }
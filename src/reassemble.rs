@@ -0,0 +1,420 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use encoding_rs::Encoding;
+
+use crate::consumer::{PartitionOffsets, Record};
+use crate::error::ParseError;
+use crate::header::LogHeader;
+use crate::resolver::{Resolution, Resolver};
+use crate::value::Value;
+
+/// 未完成的事件最多缓存的字节数，超过后不再等待后续日志块
+const MAX_PENDING_SIZE: usize = 4 * 1024 * 1024;
+
+/// 重组后的一个日志块及其解析结果
+#[derive(Debug)]
+pub struct Reassembled<'a> {
+    /// 实际解析的记录，与未完成的事件拼接过时 value 是重新生成的 UTF-8 日志块
+    pub record: Cow<'a, Record>,
+    pub result: Result<Resolution<'static>, ParseError>,
+}
+
+/// 同一个文件中被日志块边界截断的事件
+#[derive(Debug)]
+struct Pending {
+    /// 事件开始处的头部，file_line 和 file_offset 指向事件的第一行
+    header: LogHeader,
+    content: String,
+    /// 紧接着的日志块的 file_offset
+    next_file_offset: u64,
+    /// 原始日志块的编码，`header` 已经改成 UTF-8，file_offset 仍按这个编码计算
+    encoding: &'static Encoding,
+    /// 产生这个事件的记录，不含 value
+    record: Record,
+    /// 事件开始处所在记录的 offset，事件跨多个记录时早于 `record.offset`
+    first_offset: i64,
+    since: Instant,
+}
+
+/// 按 (hostname, path) 重组跨日志块的事件
+///
+/// 日志块没有结束在事件边界上时，最后一个事件可能还没有结束，先缓存起来，
+/// 等下一个 file_offset 相邻的日志块到达后拼接在它的开头再一起解析；
+/// 下一个日志块不相邻或者超过 `timeout` 没有到达时，按已有内容输出。
+/// 缓存的事件所在记录及之后的 offset 不能提交，见 `pending_offsets`，进程退出前需要调用 `flush_all`
+pub struct Reassembler {
+    timeout: Duration,
+    pending: HashMap<(String, String), Pending>,
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// 解析一条记录，返回这条记录和被它挤出的未完成事件的解析结果
+    ///
+    /// 头部没有 path、file_offset 或 data_length 的记录直接解析
+    pub fn push<'a>(&mut self, resolver: &Resolver, record: &'a Record) -> Vec<Reassembled<'a>> {
+        let (header, content) = match resolver.decode_record(record) {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                return vec![Reassembled {
+                    record: Cow::Borrowed(record),
+                    result: Err(error),
+                }];
+            }
+        };
+        let (Some(key), Some(file_offset), Some(data_length)) =
+            (header.source_file(), header.file_offset, header.data_length)
+        else {
+            return vec![Reassembled {
+                record: Cow::Borrowed(record),
+                result: resolver
+                    .resolve_decoded(header, content)
+                    .map(Resolution::into_owned),
+            }];
+        };
+
+        let mut out = Vec::new();
+        // 拼接前未完成事件的长度和开始处所在记录的 offset
+        let mut joined = None;
+        let mut encoding = header.encode;
+        let (header, content) = match self.pending.remove(&key) {
+            Some(pending) if pending.next_file_offset == file_offset => {
+                joined = Some((pending.content.len(), pending.first_offset));
+                encoding = pending.encoding;
+                let mut combined = pending.content;
+                combined.push_str(&content);
+                (pending.header, Cow::Owned(combined))
+            }
+            other => {
+                if let Some(pending) = other {
+                    log::debug!(
+                        "{}:{} expected offset {}, got {file_offset}",
+                        key.0,
+                        key.1,
+                        pending.next_file_offset
+                    );
+                    out.push(flush(resolver, pending));
+                }
+                (header, content)
+            }
+        };
+
+        let result = resolver
+            .resolve_decoded(header.clone(), Cow::Borrowed(&content))
+            .map(|mut resolution| {
+                // 文件偏移溢出的日志块视为不相邻，不再等待后续日志块
+                if let Some(tail) = resolution.last_event.clone()
+                    && content.len() - tail.range.start <= MAX_PENDING_SIZE
+                    && let Some(next_file_offset) = file_offset.checked_add(data_length)
+                {
+                    resolution.retain_before_line(tail.line_offset);
+                    let mut tail_header = utf8_header(&header);
+                    tail_header.file_line = header
+                        .file_line
+                        .map(|file_line| file_line + tail.line_offset as u64);
+                    // 拼接后的内容已经是 UTF-8，偏移要按原始编码重新计算
+                    let byte_offset = if joined.is_some() {
+                        encoding.encode(&content[..tail.range.start]).0.len()
+                    } else {
+                        tail.byte_offset
+                    };
+                    tail_header.file_offset = header
+                        .file_offset
+                        .and_then(|offset| offset.checked_add(byte_offset as u64));
+                    let first_offset = match joined {
+                        Some((len, first_offset)) if tail.range.start < len => first_offset,
+                        _ => record.offset,
+                    };
+                    self.pending.insert(
+                        key,
+                        Pending {
+                            header: tail_header,
+                            content: content[tail.range.start..].to_string(),
+                            next_file_offset,
+                            encoding,
+                            record: with_value(record, Vec::new()),
+                            first_offset,
+                            since: Instant::now(),
+                        },
+                    );
+                }
+                resolution.into_owned()
+            });
+        let record = if joined.is_some() {
+            let value = [
+                utf8_header(&header).to_bytes(),
+                content.into_owned().into_bytes(),
+            ];
+            Cow::Owned(with_value(record, value.concat()))
+        } else {
+            Cow::Borrowed(record)
+        };
+        out.push(Reassembled { record, result });
+        out
+    }
+
+    /// 每个分区最早的未完成事件所在记录的 offset，提交的 offset 不能超过它，
+    /// 否则重启后这些事件会丢失
+    pub fn pending_offsets(&self) -> PartitionOffsets {
+        let mut offsets = PartitionOffsets::default();
+        for pending in self.pending.values() {
+            offsets.pending(
+                &pending.record.topic,
+                pending.record.partition,
+                pending.first_offset,
+            );
+        }
+        offsets
+    }
+
+    /// 输出超过 `timeout` 仍未等到后续日志块的事件
+    pub fn flush_expired(&mut self, resolver: &Resolver) -> Vec<Reassembled<'static>> {
+        let timeout = self.timeout;
+        let expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.since.elapsed() >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .map(|pending| flush(resolver, pending))
+            .collect()
+    }
+
+    /// 输出全部未完成的事件
+    pub fn flush_all(&mut self, resolver: &Resolver) -> Vec<Reassembled<'static>> {
+        self.pending
+            .drain()
+            .map(|(_, pending)| flush(resolver, pending))
+            .collect()
+    }
+}
+
+/// 内容已经解压、解码为字符串后使用的头部
+fn utf8_header(header: &LogHeader) -> LogHeader {
+    let mut header = header.clone();
+    header.encode = encoding_rs::UTF_8;
+    header
+        .attr
+        .insert("encode".to_string(), Value::from("UTF-8"));
+    header
+        .attr
+        .insert("compress_algorithm".to_string(), Value::from("null"));
+    header.data_length = None;
+    header.attr.remove("file_line_count");
+    header
+}
+
+fn with_value(record: &Record, value: Vec<u8>) -> Record {
+    Record {
+        key: record.key.clone(),
+        value,
+        timestamp: record.timestamp,
+        topic: record.topic.clone(),
+        partition: record.partition,
+        offset: record.offset,
+    }
+}
+
+fn flush(resolver: &Resolver, pending: Pending) -> Reassembled<'static> {
+    let result = resolver
+        .resolve_decoded(pending.header.clone(), Cow::Borrowed(&pending.content))
+        .map(Resolution::into_owned);
+    Reassembled {
+        record: Cow::Owned(Record {
+            value: [pending.header.to_bytes(), pending.content.into_bytes()].concat(),
+            ..pending.record
+        }),
+        result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header::encode_header;
+    use crate::rule_cache::RuleSet;
+
+    use super::*;
+
+    const RULES: &str = r#"
+sys_subsys_config:
+- {id: 1, sys_code: SYS, subsys_code: APP}
+subsys_log_parser:
+- {id: 1, subsys_code: APP, log_parser_rule_id: 1, status: true, log_split: "\n", source_topic: raw}
+log_parser_rule:
+- {id: 1, status: true}
+log_parser_pattern:
+- {id: 1, log_parser_rule_id: 1, pattern: '^(?P<level>INFO|ERROR) (?P<message>.*)$'}
+log_parser_field:
+- {id: 1, log_parser_rule_id: 1, name_in_capture: level, type: 0}
+"#;
+
+    fn resolver() -> Resolver {
+        Resolver::from_rule_set(RuleSet::from_rows(serde_yaml::from_str(RULES).unwrap()))
+    }
+
+    fn record(offset: i64, file_offset: u64, content: &str) -> Record {
+        let header = encode_header([
+            ("subsyscode", "APP"),
+            ("hostname", "host"),
+            ("path", "/a.log"),
+            ("file_offset", &file_offset.to_string()),
+            ("data_length", &content.len().to_string()),
+        ]);
+        Record {
+            key: "key".to_string(),
+            value: [header.as_bytes(), content.as_bytes()].concat(),
+            timestamp: 0,
+            topic: "raw".to_string(),
+            partition: 0,
+            offset,
+        }
+    }
+
+    fn contents(reassembled: &Reassembled) -> Vec<String> {
+        let resolution = reassembled.result.as_ref().unwrap();
+        resolution
+            .logs
+            .iter()
+            .map(|log| log.log_content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn joins_event_across_adjacent_blocks() {
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        let first = record(5, 0, "INFO a\nINFO b");
+        let out = reassembler.push(&resolver, &first);
+        assert_eq!(out.len(), 1);
+        assert_eq!(contents(&out[0]), vec!["INFO a"]);
+        assert_eq!(reassembler.pending_offsets().get("raw", 0), Some(5));
+
+        // 第二个日志块没有换行，未完成的事件仍然从第一个记录开始
+        let second = record(6, 13, "cd");
+        let out = reassembler.push(&resolver, &second);
+        assert!(contents(&out[0]).is_empty());
+        assert!(matches!(out[0].record, Cow::Owned(_)));
+        assert_eq!(reassembler.pending_offsets().get("raw", 0), Some(5));
+
+        let third = record(7, 15, "e\nINFO f");
+        let out = reassembler.push(&resolver, &third);
+        assert_eq!(contents(&out[0]), vec!["INFO bcde"]);
+        assert_eq!(reassembler.pending_offsets().get("raw", 0), Some(7));
+
+        let mut handled = PartitionOffsets::default();
+        handled.handled(&third);
+        assert_eq!(
+            handled.capped(&reassembler.pending_offsets()).get("raw", 0),
+            Some(7)
+        );
+
+        let out = reassembler.flush_all(&resolver);
+        assert_eq!(out.len(), 1);
+        assert_eq!(contents(&out[0]), vec!["INFO f"]);
+        assert!(reassembler.pending_offsets().is_empty());
+        assert_eq!(
+            handled.capped(&reassembler.pending_offsets()).get("raw", 0),
+            Some(8)
+        );
+    }
+
+    #[test]
+    fn flushes_pending_event_when_next_block_is_not_adjacent() {
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        reassembler.push(&resolver, &record(1, 0, "INFO a\nINFO b"));
+        let second = record(2, 100, "INFO c\nINFO d");
+        let out = reassembler.push(&resolver, &second);
+        assert_eq!(out.len(), 2);
+        assert_eq!(contents(&out[0]), vec!["INFO b"]);
+        assert_eq!(out[0].record.offset, 1);
+        let header = resolver.decode_record(&out[0].record).unwrap().0;
+        assert_eq!(header.file_offset, Some(7));
+        assert_eq!(contents(&out[1]), vec!["INFO c"]);
+        assert_eq!(reassembler.pending_offsets().get("raw", 0), Some(2));
+    }
+
+    #[test]
+    fn flushes_expired_events() {
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        reassembler.push(&resolver, &record(1, 0, "INFO a\nINFO b"));
+        assert!(reassembler.flush_expired(&resolver).is_empty());
+
+        let mut reassembler = Reassembler::new(Duration::ZERO);
+        reassembler.push(&resolver, &record(1, 0, "INFO a\nINFO b"));
+        let out = reassembler.flush_expired(&resolver);
+        assert_eq!(out.len(), 1);
+        assert_eq!(contents(&out[0]), vec!["INFO b"]);
+        assert!(reassembler.pending_offsets().is_empty());
+    }
+
+    #[test]
+    fn block_ending_on_event_boundary_is_not_cached() {
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        let first = record(1, 0, "INFO a\nINFO b\n");
+        let out = reassembler.push(&resolver, &first);
+        assert_eq!(out.len(), 1);
+        assert_eq!(contents(&out[0]), vec!["INFO a", "INFO b"]);
+        assert!(reassembler.pending_offsets().is_empty());
+
+        let second = record(2, 14, "INFO c");
+        let out = reassembler.push(&resolver, &second);
+        assert_eq!(out.len(), 1);
+        assert!(contents(&out[0]).is_empty());
+        assert!(matches!(out[0].record, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn overflowing_file_offset_is_not_cached() {
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        let overflowing = record(1, u64::MAX - 1, "INFO a\nINFO b");
+        let out = reassembler.push(&resolver, &overflowing);
+        assert_eq!(contents(&out[0]), vec!["INFO a", "INFO b"]);
+        assert!(reassembler.pending_offsets().is_empty());
+    }
+
+    #[test]
+    fn joined_file_offset_counts_source_bytes() {
+        let gbk_record = |offset, file_offset: u64, content: &str| {
+            let bytes = encoding_rs::GBK.encode(content).0.into_owned();
+            let header = encode_header([
+                ("subsyscode", "APP"),
+                ("encode", "GBK"),
+                ("hostname", "host"),
+                ("path", "/a.log"),
+                ("file_offset", &file_offset.to_string()),
+                ("data_length", &bytes.len().to_string()),
+            ]);
+            Record {
+                value: [header.as_bytes(), &bytes].concat(),
+                ..record(offset, file_offset, "")
+            }
+        };
+        let resolver = resolver();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        // GBK 中每个汉字两个字节
+        reassembler.push(&resolver, &gbk_record(1, 0, "INFO 一\nINFO 二"));
+        let second = gbk_record(2, 15, "三\nINFO 四");
+        let out = reassembler.push(&resolver, &second);
+        assert_eq!(contents(&out[0]), vec!["INFO 二三"]);
+
+        let out = reassembler.flush_all(&resolver);
+        assert_eq!(contents(&out[0]), vec!["INFO 四"]);
+        let header = resolver.decode_record(&out[0].record).unwrap().0;
+        assert_eq!(header.file_offset, Some(18));
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use super::{RuleRepository, RuleRows};
+use crate::models::*;
+use crate::rule_file;
+
+/// 内存中的规则存储，可以在代码中构造，也可以从规则文件读取
+///
+/// 查询语义与数据库一致：列表按 id 排序，subsys_log_parser 只返回启用的记录
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRuleRepository {
+    rows: RuleRows,
+}
+
+impl MemoryRuleRepository {
+    pub fn new(mut rows: RuleRows) -> Self {
+        rows.sys_subsys_config.sort_by_key(|r| r.id);
+        rows.subsys_log_parser.sort_by_key(|r| r.id);
+        rows.log_parser_rule.sort_by_key(|r| r.id);
+        rows.log_parser_pattern.sort_by_key(|r| r.id);
+        rows.log_parser_field.sort_by_key(|r| r.id);
+        Self { rows }
+    }
+
+    /// 读取 YAML/TOML/JSON 格式的规则文件
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self::new(rule_file::read(path)?))
+    }
+
+    pub fn rows(&self) -> &RuleRows {
+        &self.rows
+    }
+}
+
+impl RuleRepository for MemoryRuleRepository {
+    fn sys_subsys_config_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Option<SysSubsysConfig>> {
+        Ok(self
+            .rows
+            .sys_subsys_config
+            .iter()
+            .find(|r| r.subsys_code == subsys_code)
+            .cloned())
+    }
+
+    fn sys_subsys_configs(&mut self) -> anyhow::Result<Vec<SysSubsysConfig>> {
+        Ok(self.rows.sys_subsys_config.clone())
+    }
+
+    fn subsys_log_parsers_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(self
+            .rows
+            .subsys_log_parser
+            .iter()
+            .filter(|r| r.status && r.subsys_code == subsys_code)
+            .cloned()
+            .collect())
+    }
+
+    fn subsys_log_parsers(&mut self) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(self
+            .rows
+            .subsys_log_parser
+            .iter()
+            .filter(|r| r.status)
+            .cloned()
+            .collect())
+    }
+
+    fn log_parser_rule_by_id(&mut self, id: u64) -> anyhow::Result<Option<LogParserRule>> {
+        Ok(self
+            .rows
+            .log_parser_rule
+            .iter()
+            .find(|r| r.id == id)
+            .cloned())
+    }
+
+    fn log_parser_rules(&mut self) -> anyhow::Result<Vec<LogParserRule>> {
+        Ok(self.rows.log_parser_rule.clone())
+    }
+
+    fn log_parser_patterns_by_rule_id(
+        &mut self,
+        log_parser_rule_id: u64,
+    ) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(self
+            .rows
+            .log_parser_pattern
+            .iter()
+            .filter(|r| r.log_parser_rule_id == log_parser_rule_id)
+            .cloned()
+            .collect())
+    }
+
+    fn log_parser_patterns(&mut self) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(self.rows.log_parser_pattern.clone())
+    }
+
+    fn log_parser_field_by_rule_id_and_name_in_capture(
+        &mut self,
+        log_parser_rule_id: u64,
+        name_in_capture: &str,
+    ) -> anyhow::Result<Option<LogParserField>> {
+        Ok(self
+            .rows
+            .log_parser_field
+            .iter()
+            .find(|r| {
+                r.log_parser_rule_id == log_parser_rule_id && r.name_in_capture == name_in_capture
+            })
+            .cloned())
+    }
+
+    fn log_parser_fields(&mut self) -> anyhow::Result<Vec<LogParserField>> {
+        Ok(self.rows.log_parser_field.clone())
+    }
+
+    fn dump(&mut self) -> anyhow::Result<RuleRows> {
+        Ok(self.rows.clone())
+    }
+
+    fn replace_all(&mut self, rows: &RuleRows) -> anyhow::Result<()> {
+        *self = Self::new(rows.clone());
+        Ok(())
+    }
+}
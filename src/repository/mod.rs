@@ -0,0 +1,101 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ParseError;
+use crate::models::*;
+
+pub mod memory;
+pub mod mysql;
+pub mod sqlite;
+
+pub use memory::MemoryRuleRepository;
+pub use sqlite::SqliteRuleRepository;
+
+/// 规则相关的五张表的全部行
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleRows {
+    pub sys_subsys_config: Vec<SysSubsysConfig>,
+    pub subsys_log_parser: Vec<SubsysLogParser>,
+    pub log_parser_rule: Vec<LogParserRule>,
+    pub log_parser_pattern: Vec<LogParserPattern>,
+    pub log_parser_field: Vec<LogParserField>,
+}
+
+/// 规则的存储，覆盖 dao 中的全部查询
+///
+/// 列表查询按 id 排序，subsys_log_parser 只返回启用的记录
+pub trait RuleRepository: Send {
+    fn sys_subsys_config_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Option<SysSubsysConfig>>;
+
+    fn sys_subsys_configs(&mut self) -> anyhow::Result<Vec<SysSubsysConfig>>;
+
+    fn subsys_log_parsers_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Vec<SubsysLogParser>>;
+
+    fn subsys_log_parsers(&mut self) -> anyhow::Result<Vec<SubsysLogParser>>;
+
+    fn log_parser_rule_by_id(&mut self, id: u64) -> anyhow::Result<Option<LogParserRule>>;
+
+    fn log_parser_rules(&mut self) -> anyhow::Result<Vec<LogParserRule>>;
+
+    fn log_parser_patterns_by_rule_id(
+        &mut self,
+        log_parser_rule_id: u64,
+    ) -> anyhow::Result<Vec<LogParserPattern>>;
+
+    fn log_parser_patterns(&mut self) -> anyhow::Result<Vec<LogParserPattern>>;
+
+    fn log_parser_field_by_rule_id_and_name_in_capture(
+        &mut self,
+        log_parser_rule_id: u64,
+        name_in_capture: &str,
+    ) -> anyhow::Result<Option<LogParserField>>;
+
+    fn log_parser_fields(&mut self) -> anyhow::Result<Vec<LogParserField>>;
+
+    /// 导出全部行，包括未启用的 subsys_log_parser
+    fn dump(&mut self) -> anyhow::Result<RuleRows>;
+
+    /// 在一个事务中用 `rows` 替换五张表的全部内容，保留行的 id
+    fn replace_all(&mut self, rows: &RuleRows) -> anyhow::Result<()>;
+
+    /// 一次读出解析需要的全部规则
+    fn load_all(&mut self) -> anyhow::Result<RuleRows> {
+        Ok(RuleRows {
+            sys_subsys_config: self.sys_subsys_configs()?,
+            subsys_log_parser: self.subsys_log_parsers()?,
+            log_parser_rule: self.log_parser_rules()?,
+            log_parser_pattern: self.log_parser_patterns()?,
+            log_parser_field: self.log_parser_fields()?,
+        })
+    }
+}
+
+/// 按 DATABASE_URL 的 scheme 选择存储：`mysql://`、`sqlite://<路径>`，
+/// 或 `file://<路径>` 从 YAML/TOML/JSON 规则文件读取而不使用数据库
+pub fn connect(database_url: &str) -> anyhow::Result<Box<dyn RuleRepository>> {
+    if database_url.starts_with("mysql://") {
+        Ok(Box::new(
+            mysql::establish(database_url).map_err(ParseError::repository)?,
+        ))
+    } else if let Some(path) = database_url
+        .strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("sqlite:"))
+    {
+        Ok(Box::new(
+            SqliteRuleRepository::establish(path).map_err(ParseError::repository)?,
+        ))
+    } else if let Some(path) = database_url.strip_prefix("file://") {
+        Ok(Box::new(MemoryRuleRepository::from_file(path)?))
+    } else {
+        Err(anyhow!(
+            "unsupported DATABASE_URL {database_url:?}, expected mysql://, sqlite:// or file://"
+        ))
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::Context;
+use diesel::{
+    Connection, ExpressionMethods, MysqlConnection, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+
+use super::{RuleRepository, RuleRows};
+use crate::dao::{
+    log_parser_field_dao, log_parser_pattern_dao, log_parser_rule_dao,
+    subsys_log_parser_config_dao, sys_subsys_config_dao,
+};
+use crate::models::*;
+use crate::schema;
+
+pub fn establish(database_url: &str) -> anyhow::Result<MysqlConnection> {
+    MysqlConnection::establish(database_url)
+        .with_context(|| format!("failed to connect to {database_url}"))
+}
+
+/// 现有的 MySQL 存储，使用 `schema` 中的表定义
+impl RuleRepository for MysqlConnection {
+    fn sys_subsys_config_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Option<SysSubsysConfig>> {
+        Ok(sys_subsys_config_dao::query_by_subsys_code(
+            self,
+            subsys_code,
+        )?)
+    }
+
+    fn sys_subsys_configs(&mut self) -> anyhow::Result<Vec<SysSubsysConfig>> {
+        Ok(sys_subsys_config_dao::query_all(self)?)
+    }
+
+    fn subsys_log_parsers_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(subsys_log_parser_config_dao::query_by_subsys_code(
+            self,
+            subsys_code,
+        )?)
+    }
+
+    fn subsys_log_parsers(&mut self) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(subsys_log_parser_config_dao::query_all(self)?)
+    }
+
+    fn log_parser_rule_by_id(&mut self, id: u64) -> anyhow::Result<Option<LogParserRule>> {
+        Ok(log_parser_rule_dao::query_by_id(self, id)?)
+    }
+
+    fn log_parser_rules(&mut self) -> anyhow::Result<Vec<LogParserRule>> {
+        Ok(log_parser_rule_dao::query_all(self)?)
+    }
+
+    fn log_parser_patterns_by_rule_id(
+        &mut self,
+        log_parser_rule_id: u64,
+    ) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(log_parser_pattern_dao::query_by_log_parser_rule_id(
+            self,
+            log_parser_rule_id,
+        )?)
+    }
+
+    fn log_parser_patterns(&mut self) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(log_parser_pattern_dao::query_all(self)?)
+    }
+
+    fn log_parser_field_by_rule_id_and_name_in_capture(
+        &mut self,
+        log_parser_rule_id: u64,
+        name_in_capture: &str,
+    ) -> anyhow::Result<Option<LogParserField>> {
+        Ok(
+            log_parser_field_dao::query_by_log_parser_rule_id_and_name_in_capture(
+                self,
+                log_parser_rule_id,
+                name_in_capture,
+            )?,
+        )
+    }
+
+    fn log_parser_fields(&mut self) -> anyhow::Result<Vec<LogParserField>> {
+        Ok(log_parser_field_dao::query_all(self)?)
+    }
+
+    fn dump(&mut self) -> anyhow::Result<RuleRows> {
+        Ok(RuleRows {
+            sys_subsys_config: self.sys_subsys_configs()?,
+            subsys_log_parser: schema::subsys_log_parser::table
+                .order(schema::subsys_log_parser::id.asc())
+                .select(SubsysLogParser::as_select())
+                .get_results(self)?,
+            log_parser_rule: self.log_parser_rules()?,
+            log_parser_pattern: self.log_parser_patterns()?,
+            log_parser_field: self.log_parser_fields()?,
+        })
+    }
+
+    fn replace_all(&mut self, rows: &RuleRows) -> anyhow::Result<()> {
+        self.transaction(|conn| {
+            diesel::delete(schema::log_parser_field::table).execute(conn)?;
+            diesel::delete(schema::log_parser_pattern::table).execute(conn)?;
+            diesel::delete(schema::subsys_log_parser::table).execute(conn)?;
+            diesel::delete(schema::log_parser_rule::table).execute(conn)?;
+            diesel::delete(schema::sys_subsys_config::table).execute(conn)?;
+            diesel::insert_into(schema::sys_subsys_config::table)
+                .values(&rows.sys_subsys_config)
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_rule::table)
+                .values(&rows.log_parser_rule)
+                .execute(conn)?;
+            diesel::insert_into(schema::subsys_log_parser::table)
+                .values(&rows.subsys_log_parser)
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_pattern::table)
+                .values(&rows.log_parser_pattern)
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_field::table)
+                .values(&rows.log_parser_field)
+                .execute(conn)?;
+            diesel::QueryResult::Ok(())
+        })?;
+        Ok(())
+    }
+}
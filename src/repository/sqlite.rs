@@ -0,0 +1,495 @@
+use anyhow::Context;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+    SelectableHelper, SqliteConnection, connection::SimpleConnection,
+};
+
+use super::{RuleRepository, RuleRows};
+use crate::models::*;
+
+/// SQLite 没有无符号整数，id 使用 BigInt，其余与 `crate::schema` 一致
+mod schema {
+    diesel::table! {
+        log_parser_field (id) {
+            id -> BigInt,
+            log_parser_rule_id -> BigInt,
+            name -> Nullable<Text>,
+            name_in_capture -> Text,
+            #[sql_name = "type"]
+            type_ -> Integer,
+            format_pattern -> Nullable<Text>,
+            default_val -> Nullable<Text>,
+            is_sensitive -> Nullable<Bool>,
+            mask_strategy -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        log_parser_pattern (id) {
+            id -> BigInt,
+            log_parser_rule_id -> BigInt,
+            name -> Nullable<Text>,
+            pattern -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        log_parser_rule (id) {
+            id -> BigInt,
+            name -> Nullable<Text>,
+            status -> Bool,
+            chinese_name -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        subsys_log_parser (id) {
+            id -> BigInt,
+            subsys_code -> Text,
+            log_parser_rule_id -> BigInt,
+            file_name -> Nullable<Text>,
+            status -> Bool,
+            log_split -> Nullable<Text>,
+            source_topic -> Text,
+            use_header_pattern -> Bool,
+        }
+    }
+
+    diesel::table! {
+        sys_subsys_config (id) {
+            id -> BigInt,
+            sys_code -> Text,
+            sys_name -> Nullable<Text>,
+            subsys_code -> Text,
+            subsys_name -> Nullable<Text>,
+        }
+    }
+}
+
+/// 建表语句，与 MySQL 的 migrations 执行后的结构一致
+pub const CREATE_TABLES: &str = r#"
+create table if not exists sys_subsys_config
+(
+    id          integer primary key autoincrement,
+    sys_code    text not null,
+    sys_name    text null,
+    subsys_code text not null,
+    subsys_name text null,
+    unique (sys_code, subsys_code)
+);
+create table if not exists subsys_log_parser
+(
+    id                 integer primary key autoincrement,
+    subsys_code        text    not null,
+    log_parser_rule_id integer not null,
+    file_name          text    null,
+    status             boolean not null,
+    log_split          text    null,
+    source_topic       text    not null,
+    use_header_pattern boolean not null default 0,
+    unique (log_parser_rule_id, file_name, subsys_code)
+);
+create table if not exists log_parser_rule
+(
+    id           integer primary key autoincrement,
+    name         text    null,
+    status       boolean not null,
+    chinese_name text    null
+);
+create table if not exists log_parser_pattern
+(
+    id                 integer primary key autoincrement,
+    log_parser_rule_id integer not null,
+    name               text    null,
+    pattern            text    null
+);
+create table if not exists log_parser_field
+(
+    id                 integer primary key autoincrement,
+    log_parser_rule_id integer not null,
+    name               text    null,
+    name_in_capture    text    not null,
+    type               integer not null,
+    format_pattern     text    null,
+    default_val        text    null,
+    is_sensitive       boolean null,
+    mask_strategy      text    null
+);
+"#;
+
+#[derive(diesel::Queryable, diesel::Selectable, diesel::Insertable)]
+#[diesel(table_name = schema::sys_subsys_config)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct SysSubsysConfigRow {
+    id: i64,
+    sys_code: String,
+    sys_name: Option<String>,
+    subsys_code: String,
+    subsys_name: Option<String>,
+}
+
+impl From<SysSubsysConfigRow> for SysSubsysConfig {
+    fn from(row: SysSubsysConfigRow) -> Self {
+        Self {
+            id: row.id as u64,
+            sys_code: row.sys_code,
+            sys_name: row.sys_name,
+            subsys_code: row.subsys_code,
+            subsys_name: row.subsys_name,
+        }
+    }
+}
+
+impl From<&SysSubsysConfig> for SysSubsysConfigRow {
+    fn from(model: &SysSubsysConfig) -> Self {
+        Self {
+            id: model.id as i64,
+            sys_code: model.sys_code.clone(),
+            sys_name: model.sys_name.clone(),
+            subsys_code: model.subsys_code.clone(),
+            subsys_name: model.subsys_name.clone(),
+        }
+    }
+}
+
+#[derive(diesel::Queryable, diesel::Selectable, diesel::Insertable)]
+#[diesel(table_name = schema::subsys_log_parser)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct SubsysLogParserRow {
+    id: i64,
+    subsys_code: String,
+    log_parser_rule_id: i64,
+    file_name: Option<String>,
+    status: bool,
+    log_split: Option<String>,
+    source_topic: String,
+    use_header_pattern: bool,
+}
+
+impl From<SubsysLogParserRow> for SubsysLogParser {
+    fn from(row: SubsysLogParserRow) -> Self {
+        Self {
+            id: row.id as u64,
+            subsys_code: row.subsys_code,
+            log_parser_rule_id: row.log_parser_rule_id as u64,
+            file_name: row.file_name,
+            status: row.status,
+            log_split: row.log_split,
+            source_topic: row.source_topic,
+            use_header_pattern: row.use_header_pattern,
+        }
+    }
+}
+
+impl From<&SubsysLogParser> for SubsysLogParserRow {
+    fn from(model: &SubsysLogParser) -> Self {
+        Self {
+            id: model.id as i64,
+            subsys_code: model.subsys_code.clone(),
+            log_parser_rule_id: model.log_parser_rule_id as i64,
+            file_name: model.file_name.clone(),
+            status: model.status,
+            log_split: model.log_split.clone(),
+            source_topic: model.source_topic.clone(),
+            use_header_pattern: model.use_header_pattern,
+        }
+    }
+}
+
+#[derive(diesel::Queryable, diesel::Selectable, diesel::Insertable)]
+#[diesel(table_name = schema::log_parser_rule)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct LogParserRuleRow {
+    id: i64,
+    name: Option<String>,
+    status: bool,
+    chinese_name: Option<String>,
+}
+
+impl From<LogParserRuleRow> for LogParserRule {
+    fn from(row: LogParserRuleRow) -> Self {
+        Self {
+            id: row.id as u64,
+            name: row.name,
+            status: row.status,
+            chinese_name: row.chinese_name,
+        }
+    }
+}
+
+impl From<&LogParserRule> for LogParserRuleRow {
+    fn from(model: &LogParserRule) -> Self {
+        Self {
+            id: model.id as i64,
+            name: model.name.clone(),
+            status: model.status,
+            chinese_name: model.chinese_name.clone(),
+        }
+    }
+}
+
+#[derive(diesel::Queryable, diesel::Selectable, diesel::Insertable)]
+#[diesel(table_name = schema::log_parser_pattern)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct LogParserPatternRow {
+    id: i64,
+    log_parser_rule_id: i64,
+    name: Option<String>,
+    pattern: Option<String>,
+}
+
+impl From<LogParserPatternRow> for LogParserPattern {
+    fn from(row: LogParserPatternRow) -> Self {
+        Self {
+            id: row.id as u64,
+            log_parser_rule_id: row.log_parser_rule_id as u64,
+            name: row.name,
+            pattern: row.pattern,
+        }
+    }
+}
+
+impl From<&LogParserPattern> for LogParserPatternRow {
+    fn from(model: &LogParserPattern) -> Self {
+        Self {
+            id: model.id as i64,
+            log_parser_rule_id: model.log_parser_rule_id as i64,
+            name: model.name.clone(),
+            pattern: model.pattern.clone(),
+        }
+    }
+}
+
+#[derive(diesel::Queryable, diesel::Selectable, diesel::Insertable)]
+#[diesel(table_name = schema::log_parser_field)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct LogParserFieldRow {
+    id: i64,
+    log_parser_rule_id: i64,
+    name: Option<String>,
+    name_in_capture: String,
+    type_: i32,
+    format_pattern: Option<String>,
+    default_val: Option<String>,
+    is_sensitive: Option<bool>,
+    mask_strategy: Option<String>,
+}
+
+impl From<LogParserFieldRow> for LogParserField {
+    fn from(row: LogParserFieldRow) -> Self {
+        Self {
+            id: row.id as u64,
+            log_parser_rule_id: row.log_parser_rule_id as u64,
+            name: row.name,
+            name_in_capture: row.name_in_capture,
+            type_: row.type_,
+            format_pattern: row.format_pattern,
+            default_val: row.default_val,
+            is_sensitive: row.is_sensitive,
+            mask_strategy: row.mask_strategy,
+        }
+    }
+}
+
+impl From<&LogParserField> for LogParserFieldRow {
+    fn from(model: &LogParserField) -> Self {
+        Self {
+            id: model.id as i64,
+            log_parser_rule_id: model.log_parser_rule_id as i64,
+            name: model.name.clone(),
+            name_in_capture: model.name_in_capture.clone(),
+            type_: model.type_,
+            format_pattern: model.format_pattern.clone(),
+            default_val: model.default_val.clone(),
+            is_sensitive: model.is_sensitive,
+            mask_strategy: model.mask_strategy.clone(),
+        }
+    }
+}
+
+fn into_models<R, M: From<R>>(rows: Vec<R>) -> Vec<M> {
+    rows.into_iter().map(M::from).collect()
+}
+
+fn into_rows<'a, M: 'a, R: From<&'a M>>(models: &'a [M]) -> Vec<R> {
+    models.iter().map(R::from).collect()
+}
+
+/// 基于 SQLite 的规则存储，适合不方便部署 MySQL 的场景
+pub struct SqliteRuleRepository {
+    conn: SqliteConnection,
+}
+
+impl SqliteRuleRepository {
+    /// 打开数据库文件，表不存在时自动创建
+    pub fn establish(path: &str) -> anyhow::Result<Self> {
+        let mut conn = SqliteConnection::establish(path)
+            .with_context(|| format!("failed to open sqlite database {path}"))?;
+        conn.batch_execute(CREATE_TABLES)
+            .context("failed to create sqlite tables")?;
+        Ok(Self { conn })
+    }
+
+    pub fn connection(&mut self) -> &mut SqliteConnection {
+        &mut self.conn
+    }
+}
+
+impl RuleRepository for SqliteRuleRepository {
+    fn sys_subsys_config_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Option<SysSubsysConfig>> {
+        Ok(schema::sys_subsys_config::table
+            .filter(schema::sys_subsys_config::subsys_code.eq(subsys_code))
+            .select(SysSubsysConfigRow::as_select())
+            .first(&mut self.conn)
+            .optional()?
+            .map(SysSubsysConfig::from))
+    }
+
+    fn sys_subsys_configs(&mut self) -> anyhow::Result<Vec<SysSubsysConfig>> {
+        Ok(into_models(
+            schema::sys_subsys_config::table
+                .order(schema::sys_subsys_config::id.asc())
+                .select(SysSubsysConfigRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn subsys_log_parsers_by_subsys_code(
+        &mut self,
+        subsys_code: &str,
+    ) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(into_models(
+            schema::subsys_log_parser::table
+                .filter(schema::subsys_log_parser::subsys_code.eq(subsys_code))
+                .filter(schema::subsys_log_parser::status.eq(true))
+                .order(schema::subsys_log_parser::id.asc())
+                .select(SubsysLogParserRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn subsys_log_parsers(&mut self) -> anyhow::Result<Vec<SubsysLogParser>> {
+        Ok(into_models(
+            schema::subsys_log_parser::table
+                .filter(schema::subsys_log_parser::status.eq(true))
+                .order(schema::subsys_log_parser::id.asc())
+                .select(SubsysLogParserRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn log_parser_rule_by_id(&mut self, id: u64) -> anyhow::Result<Option<LogParserRule>> {
+        Ok(schema::log_parser_rule::table
+            .filter(schema::log_parser_rule::id.eq(id as i64))
+            .select(LogParserRuleRow::as_select())
+            .first(&mut self.conn)
+            .optional()?
+            .map(LogParserRule::from))
+    }
+
+    fn log_parser_rules(&mut self) -> anyhow::Result<Vec<LogParserRule>> {
+        Ok(into_models(
+            schema::log_parser_rule::table
+                .order(schema::log_parser_rule::id.asc())
+                .select(LogParserRuleRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn log_parser_patterns_by_rule_id(
+        &mut self,
+        log_parser_rule_id: u64,
+    ) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(into_models(
+            schema::log_parser_pattern::table
+                .filter(
+                    schema::log_parser_pattern::log_parser_rule_id.eq(log_parser_rule_id as i64),
+                )
+                .order(schema::log_parser_pattern::id.asc())
+                .select(LogParserPatternRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn log_parser_patterns(&mut self) -> anyhow::Result<Vec<LogParserPattern>> {
+        Ok(into_models(
+            schema::log_parser_pattern::table
+                .order(schema::log_parser_pattern::id.asc())
+                .select(LogParserPatternRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn log_parser_field_by_rule_id_and_name_in_capture(
+        &mut self,
+        log_parser_rule_id: u64,
+        name_in_capture: &str,
+    ) -> anyhow::Result<Option<LogParserField>> {
+        Ok(schema::log_parser_field::table
+            .filter(
+                schema::log_parser_field::log_parser_rule_id
+                    .eq(log_parser_rule_id as i64)
+                    .and(schema::log_parser_field::name_in_capture.eq(name_in_capture)),
+            )
+            .select(LogParserFieldRow::as_select())
+            .first(&mut self.conn)
+            .optional()?
+            .map(LogParserField::from))
+    }
+
+    fn log_parser_fields(&mut self) -> anyhow::Result<Vec<LogParserField>> {
+        Ok(into_models(
+            schema::log_parser_field::table
+                .order(schema::log_parser_field::id.asc())
+                .select(LogParserFieldRow::as_select())
+                .get_results(&mut self.conn)?,
+        ))
+    }
+
+    fn dump(&mut self) -> anyhow::Result<RuleRows> {
+        Ok(RuleRows {
+            sys_subsys_config: self.sys_subsys_configs()?,
+            subsys_log_parser: into_models(
+                schema::subsys_log_parser::table
+                    .order(schema::subsys_log_parser::id.asc())
+                    .select(SubsysLogParserRow::as_select())
+                    .get_results(&mut self.conn)?,
+            ),
+            log_parser_rule: self.log_parser_rules()?,
+            log_parser_pattern: self.log_parser_patterns()?,
+            log_parser_field: self.log_parser_fields()?,
+        })
+    }
+
+    fn replace_all(&mut self, rows: &RuleRows) -> anyhow::Result<()> {
+        self.conn.transaction(|conn| {
+            diesel::delete(schema::log_parser_field::table).execute(conn)?;
+            diesel::delete(schema::log_parser_pattern::table).execute(conn)?;
+            diesel::delete(schema::subsys_log_parser::table).execute(conn)?;
+            diesel::delete(schema::log_parser_rule::table).execute(conn)?;
+            diesel::delete(schema::sys_subsys_config::table).execute(conn)?;
+            diesel::insert_into(schema::sys_subsys_config::table)
+                .values(into_rows::<_, SysSubsysConfigRow>(&rows.sys_subsys_config))
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_rule::table)
+                .values(into_rows::<_, LogParserRuleRow>(&rows.log_parser_rule))
+                .execute(conn)?;
+            diesel::insert_into(schema::subsys_log_parser::table)
+                .values(into_rows::<_, SubsysLogParserRow>(&rows.subsys_log_parser))
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_pattern::table)
+                .values(into_rows::<_, LogParserPatternRow>(
+                    &rows.log_parser_pattern,
+                ))
+                .execute(conn)?;
+            diesel::insert_into(schema::log_parser_field::table)
+                .values(into_rows::<_, LogParserFieldRow>(&rows.log_parser_field))
+                .execute(conn)?;
+            diesel::QueryResult::Ok(())
+        })?;
+        Ok(())
+    }
+}
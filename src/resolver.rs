@@ -0,0 +1,660 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::compress::{Compression, DEFAULT_MAX_DECOMPRESSED_SIZE};
+use crate::consumer::Record;
+use crate::datetime::Zone;
+use crate::dialect::{HeaderDialects, RecordContext};
+use crate::error::ParseError;
+use crate::header::LogHeader;
+use crate::mask::{Masker, REDACTED};
+use crate::metadata::KeyMapping;
+use crate::models::SubsysLogParser;
+use crate::rule_cache::{CompiledParser, RuleCache, RuleSet};
+use crate::split::{Event, Splitter};
+use crate::subsys::SubsysChain;
+use crate::value::{FieldType, Value};
+
+/// 规则的来源，每次解析时取一次当前的规则快照
+pub trait RuleSource: Send + Sync {
+    /// 规则存储无法访问时返回 `ParseError::RepositoryUnavailable`
+    fn rules(&self) -> Result<Arc<RuleSet>, ParseError>;
+}
+
+/// 数据库不可用时 `RuleCache` 继续使用旧的快照
+impl RuleSource for RuleCache {
+    fn rules(&self) -> Result<Arc<RuleSet>, ParseError> {
+        Ok(self.get())
+    }
+}
+
+/// 固定不变的规则
+impl RuleSource for Arc<RuleSet> {
+    fn rules(&self) -> Result<Arc<RuleSet>, ParseError> {
+        Ok(self.clone())
+    }
+}
+
+/// 解析的结果，一条事件对应一个 Log
+///
+/// 序列化后的 JSON 是下游依赖的输出格式，字段只增不减
+#[derive(Debug, Serialize)]
+pub struct Log<'a> {
+    /// 日期字段解析出的时间，没有日期字段时为解析时的时间
+    pub date_time: DateTime<Local>,
+    /// 日志块的头部，attr 中还包含该事件解析出的字段
+    pub log_header: LogHeader,
+    /// 事件原文，敏感字段已脱敏
+    pub log_content: Cow<'a, str>,
+    /// 发往的 topic，来自 subsys_log_parser.source_topic
+    #[serde(skip)]
+    pub source_topic: String,
+    /// 事件在日志块中的字节偏移
+    pub byte_offset: usize,
+    /// 事件在日志块中的行偏移
+    pub line_offset: usize,
+    /// 事件在文件中的行号，由头部的 file_line 加上行偏移得到
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_line: Option<u64>,
+    /// 解析过程中出现的问题，头部中的问题为 `ParseError::Header`，
+    /// 字段无法转换为 `ParseError::FieldConversion`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ParseError>,
+}
+
+impl Log<'_> {
+    /// 复制借用的内容，使结果不再依赖原始日志块
+    pub fn into_owned(self) -> Log<'static> {
+        Log {
+            date_time: self.date_time,
+            log_header: self.log_header,
+            log_content: Cow::Owned(self.log_content.into_owned()),
+            source_topic: self.source_topic,
+            byte_offset: self.byte_offset,
+            line_offset: self.line_offset,
+            file_line: self.file_line,
+            errors: self.errors,
+        }
+    }
+}
+
+/// 一个日志块的解析结果
+#[derive(Debug, Default)]
+pub struct Resolution<'a> {
+    pub subsys_code: String,
+    /// 尝试过的 log_parser_rule，没有 subsys_log_parser 匹配这个文件时为空
+    pub log_parser_rule_ids: Vec<u64>,
+    /// 匹配了 pattern 的事件
+    pub logs: Vec<Log<'a>>,
+    /// 没有匹配任何 pattern 的事件，都是 `ParseError::NoMatchingPattern`
+    pub unmatched: Vec<ParseError>,
+    /// 日志块中最后一个可能还没有结束的事件，日志块结束在事件边界上时为空，
+    /// 有多个 subsys_log_parser 时取开始位置最靠前的一个
+    pub last_event: Option<Event>,
+}
+
+impl Resolution<'_> {
+    /// 全部事件的错误，包括没有匹配的事件和各个 Log 中字段转换的错误
+    pub fn errors(&self) -> impl Iterator<Item = &ParseError> {
+        self.unmatched
+            .iter()
+            .chain(self.logs.iter().flat_map(|log| &log.errors))
+    }
+
+    pub fn into_owned(self) -> Resolution<'static> {
+        Resolution {
+            subsys_code: self.subsys_code,
+            log_parser_rule_ids: self.log_parser_rule_ids,
+            logs: self.logs.into_iter().map(Log::into_owned).collect(),
+            unmatched: self.unmatched,
+            last_event: self.last_event,
+        }
+    }
+
+    /// 去掉从 `line_offset` 开始的事件，用于暂缓输出未完成的最后一个事件
+    pub fn retain_before_line(&mut self, line_offset: usize) {
+        self.logs.retain(|log| log.line_offset < line_offset);
+        self.unmatched.retain(|error| match error {
+            ParseError::NoMatchingPattern {
+                line_offset: offset,
+                ..
+            } => *offset < line_offset,
+            _ => true,
+        });
+    }
+
+    /// 只保留这些行偏移上的事件，用于重放部分失败的记录
+    pub fn retain_line_offsets(&mut self, line_offsets: &[usize]) {
+        self.logs
+            .retain(|log| line_offsets.contains(&log.line_offset));
+        self.unmatched.retain(|error| match error {
+            ParseError::NoMatchingPattern { line_offset, .. } => line_offsets.contains(line_offset),
+            _ => true,
+        });
+    }
+}
+
+/// 一个事件的解析过程
+#[derive(Debug, Clone)]
+pub struct EventTrace {
+    pub subsys_log_parser_id: u64,
+    /// 事件在日志块中的行偏移
+    pub line_offset: usize,
+    /// 事件原文，未脱敏
+    pub content: String,
+    /// 匹配的 log_parser_pattern，没有匹配时为 None
+    pub pattern_id: Option<u64>,
+    /// 各命名分组捕获到的原始值，未参与匹配的分组为 None
+    pub captures: Vec<(String, Option<String>)>,
+}
+
+/// 日志解析器，把带头部的原始日志块解析为 Log
+///
+/// ```no_run
+/// # use log_resolver_rs::resolver::Resolver;
+/// # use log_resolver_rs::rule_cache::RuleSet;
+/// let resolver = Resolver::from_rule_set(RuleSet::default());
+/// let resolution = resolver.resolve(b"[[subsyscode=demo]]hello")?;
+/// for error in resolution.errors() {
+///     println!("{}: {error}", error.kind());
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub struct Resolver {
+    // 规则的来源
+    rules: Arc<dyn RuleSource>,
+    // 不带时区的日期按这个时区解释
+    datetime_zone: Zone,
+    // 敏感字段脱敏
+    masker: Masker,
+    // 解压后内容的最大字节数
+    max_decompressed_size: usize,
+    // 头部键到结构化元数据的映射
+    key_mapping: KeyMapping,
+    // 支持的头部格式
+    header_dialects: HeaderDialects,
+    // 确定子系统的来源
+    subsys_chain: SubsysChain,
+}
+
+impl Resolver {
+    /// 使用本地时区、不带 HMAC 密钥
+    pub fn new(rules: Arc<dyn RuleSource>) -> Self {
+        Self {
+            rules,
+            datetime_zone: Zone::default(),
+            masker: Masker::default(),
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+            key_mapping: KeyMapping::default(),
+            header_dialects: HeaderDialects::default(),
+            subsys_chain: SubsysChain::default(),
+        }
+    }
+
+    /// 使用固定不变的规则
+    pub fn from_rule_set(rules: RuleSet) -> Self {
+        Self::new(Arc::new(Arc::new(rules)))
+    }
+
+    pub fn with_datetime_zone(mut self, datetime_zone: Zone) -> Self {
+        self.datetime_zone = datetime_zone;
+        self
+    }
+
+    pub fn with_masker(mut self, masker: Masker) -> Self {
+        self.masker = masker;
+        self
+    }
+
+    pub fn with_max_decompressed_size(mut self, max_decompressed_size: usize) -> Self {
+        self.max_decompressed_size = max_decompressed_size;
+        self
+    }
+
+    pub fn with_key_mapping(mut self, key_mapping: KeyMapping) -> Self {
+        self.key_mapping = key_mapping;
+        self
+    }
+
+    pub fn with_header_dialects(mut self, header_dialects: HeaderDialects) -> Self {
+        self.header_dialects = header_dialects;
+        self
+    }
+
+    pub fn with_subsys_chain(mut self, subsys_chain: SubsysChain) -> Self {
+        self.subsys_chain = subsys_chain;
+        self
+    }
+
+    pub fn datetime_zone(&self) -> &Zone {
+        &self.datetime_zone
+    }
+
+    pub fn masker(&self) -> &Masker {
+        &self.masker
+    }
+
+    pub fn header_dialects(&self) -> &HeaderDialects {
+        &self.header_dialects
+    }
+
+    /// 解析一个原始日志块，头部无效或子系统未知时返回错误，
+    /// 单个事件的错误记录在结果中
+    pub fn resolve<'a>(&self, raw_log: &'a [u8]) -> Result<Resolution<'a>, ParseError> {
+        self.resolve_with_trace(RecordContext::default(), raw_log, None)
+    }
+
+    /// 和 `resolve` 一样，子系统的来源可以是记录的 key 或 topic
+    pub fn resolve_record<'a>(&self, record: &'a Record) -> Result<Resolution<'a>, ParseError> {
+        self.resolve_with_trace(record.into(), &record.value, None)
+    }
+
+    /// 和 `resolve` 一样，同时返回每个事件的解析过程，用于调试规则
+    ///
+    /// 匹配了 pattern 的事件与返回的 Log 按顺序一一对应
+    pub fn trace<'a>(
+        &self,
+        raw_log: &'a [u8],
+    ) -> Result<(Resolution<'a>, Vec<EventTrace>), ParseError> {
+        let mut traces = Vec::new();
+        let resolution =
+            self.resolve_with_trace(RecordContext::default(), raw_log, Some(&mut traces))?;
+        Ok((resolution, traces))
+    }
+
+    /// 解析头部并把内容解压、解码为字符串
+    pub fn decode<'a>(&self, raw_log: &'a [u8]) -> Result<(LogHeader, Cow<'a, str>), ParseError> {
+        self.decode_with_context(RecordContext::default(), raw_log)
+    }
+
+    /// 和 `decode` 一样，子系统的来源可以是记录的 key 或 topic
+    pub fn decode_record<'a>(
+        &self,
+        record: &'a Record,
+    ) -> Result<(LogHeader, Cow<'a, str>), ParseError> {
+        self.decode_with_context(record.into(), &record.value)
+    }
+
+    /// 只解析记录的头部，与 `decode_record` 得到的头部相同，不解压内容
+    pub fn decode_record_header(&self, record: &Record) -> Result<LogHeader, ParseError> {
+        self.decode_header_with_context(record.into(), &record.value)
+            .map(|(log_header, _)| log_header)
+    }
+
+    fn decode_header_with_context<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, &'a [u8]), ParseError> {
+        // 1、2: 按头部的格式分出头部和内容并解析头部
+        let (mut log_header, log_content_bytes) = self.header_dialects.decode(context, raw_log)?;
+        self.key_mapping.apply(&mut log_header);
+        log_header.subsys_code = self
+            .subsys_chain
+            .resolve(&log_header, context)
+            .ok_or(ParseError::MissingSubsys)?;
+        Ok((log_header, log_content_bytes))
+    }
+
+    fn decode_with_context<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+    ) -> Result<(LogHeader, Cow<'a, str>), ParseError> {
+        let (log_header, log_content_bytes) = self.decode_header_with_context(context, raw_log)?;
+        for error in &log_header.errors {
+            log::warn!(
+                "{} from {}",
+                ParseError::Header(error.clone()),
+                log_header.subsys_code
+            );
+        }
+
+        // 3: 按 compress_algorithm 解压
+        let content = Compression::from_header(&log_header)?
+            .decompress(log_content_bytes, self.max_decompressed_size)?;
+
+        // 4: 解码日志字符串
+        let (decoded_log_cow, actual_encoding, had_errors) = match content {
+            Cow::Borrowed(bytes) => log_header.encode.decode(bytes),
+            Cow::Owned(bytes) => {
+                let (decoded, actual_encoding, had_errors) = log_header.encode.decode(&bytes);
+                (
+                    Cow::Owned(decoded.into_owned()),
+                    actual_encoding,
+                    had_errors,
+                )
+            }
+        };
+        log::debug!(
+            "Decoded log: {:?}, encoding: {:?}, had_errors: {:?}",
+            decoded_log_cow,
+            actual_encoding,
+            had_errors
+        );
+        if had_errors {
+            log::warn!(
+                "Error while decoding log content from {}",
+                log_header.subsys_code
+            );
+        }
+        Ok((log_header, decoded_log_cow))
+    }
+
+    /// 按规则解析已经解码的内容，`content` 是 `log_header` 之后的全部内容
+    pub fn resolve_decoded<'a>(
+        &self,
+        log_header: LogHeader,
+        content: Cow<'a, str>,
+    ) -> Result<Resolution<'a>, ParseError> {
+        self.resolve_content(log_header, content, None)
+    }
+
+    fn resolve_with_trace<'a>(
+        &self,
+        context: RecordContext,
+        raw_log: &'a [u8],
+        traces: Option<&mut Vec<EventTrace>>,
+    ) -> Result<Resolution<'a>, ParseError> {
+        let (log_header, decoded_log_cow) = self.decode_with_context(context, raw_log)?;
+        self.resolve_content(log_header, decoded_log_cow, traces)
+    }
+
+    fn resolve_content<'a>(
+        &self,
+        log_header: LogHeader,
+        decoded_log_cow: Cow<'a, str>,
+        mut traces: Option<&mut Vec<EventTrace>>,
+    ) -> Result<Resolution<'a>, ParseError> {
+        let rules = self.rules.rules()?;
+        if rules.subsys(&log_header.subsys_code).is_none() {
+            return Err(ParseError::UnknownSubsys(log_header.subsys_code));
+        }
+        let parsers = rules.parsers_for(
+            &log_header.subsys_code,
+            log_header.filename.as_deref(),
+            log_header.path.as_deref(),
+        );
+
+        let mut resolution = Resolution {
+            log_parser_rule_ids: parsers.iter().map(|p| p.rule.id).collect(),
+            ..Resolution::default()
+        };
+        let mut logs = Vec::new();
+        let mut masked = Vec::new();
+        for parser in parsers {
+            let (parser_logs, last_event) = apply_parse_config(
+                self,
+                &log_header,
+                &decoded_log_cow,
+                &parser,
+                &mut resolution.unmatched,
+                &mut masked,
+                traces.as_deref_mut(),
+            );
+            logs.extend(parser_logs);
+            if let Some(event) = last_event
+                && resolution
+                    .last_event
+                    .as_ref()
+                    .is_none_or(|last| event.range.start < last.range.start)
+            {
+                resolution.last_event = Some(event);
+            }
+        }
+        // 任何一个 parser 认为敏感的内容在所有 Log 的原文中都脱敏
+        let masked = merge_masked(masked);
+        for (range, mut log) in logs {
+            if let Some(content) = mask_event(&decoded_log_cow, &range, &masked) {
+                log.log_content = Cow::Owned(content);
+            }
+            resolution.logs.push(log);
+        }
+        resolution.subsys_code = log_header.subsys_code;
+        Ok(resolution)
+    }
+}
+
+fn apply_parse_config<'a>(
+    resolver: &Resolver,
+    log_header: &LogHeader,
+    decoded_log_cow: &Cow<'a, str>,
+    parser: &CompiledParser,
+    unmatched: &mut Vec<ParseError>,
+    masked: &mut Vec<(Range<usize>, String)>,
+    mut traces: Option<&mut Vec<EventTrace>>,
+) -> (Vec<(Range<usize>, Log<'a>)>, Option<Event>) {
+    let subsys_log_parser_config = &parser.config;
+    let header_splitter = header_splitter(log_header, subsys_log_parser_config);
+    let splitter = header_splitter.as_deref().unwrap_or(&parser.splitter);
+
+    let file_line = log_header.file_line;
+
+    // 每个事件由第一个匹配的pattern产生一个Log
+    let mut logs = Vec::new();
+    let (events, open) = splitter.split_block(decoded_log_cow, log_header.encode);
+    let last_event = events.last().filter(|_| open).cloned();
+    for event in events {
+        let event_content = sub_cow(decoded_log_cow, event.range.clone());
+        let matched = parser
+            .patterns
+            .iter()
+            .find_map(|pattern| pattern.regex.captures(&event_content).map(|c| (pattern, c)));
+        if let Some(traces) = traces.as_deref_mut() {
+            traces.push(EventTrace {
+                subsys_log_parser_id: subsys_log_parser_config.id,
+                line_offset: event.line_offset,
+                content: event_content.to_string(),
+                pattern_id: matched.as_ref().map(|(pattern, _)| pattern.pattern.id),
+                captures: matched
+                    .as_ref()
+                    .map(|(pattern, captures)| {
+                        pattern
+                            .regex
+                            .capture_names()
+                            .flatten()
+                            .map(|name| {
+                                let value = captures.name(name).map(|m| m.as_str().to_string());
+                                (name.to_string(), value)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+        let Some((pattern, captures)) = matched else {
+            let error = ParseError::NoMatchingPattern {
+                subsys_log_parser_id: subsys_log_parser_config.id,
+                line_offset: event.line_offset,
+            };
+            log::debug!("{error}");
+            unmatched.push(error);
+            continue;
+        };
+        let pattern = &pattern.regex;
+        let mut log = Log {
+            date_time: Local::now(),
+            log_header: log_header.clone(),
+            log_content: event_content.clone(),
+            source_topic: subsys_log_parser_config.source_topic.clone(),
+            byte_offset: event.byte_offset,
+            line_offset: event.line_offset,
+            file_line: file_line.map(|line| line + event.line_offset as u64),
+            errors: log_header
+                .errors
+                .iter()
+                .cloned()
+                .map(ParseError::Header)
+                .collect(),
+        };
+
+        for group_name in pattern.capture_names().flatten() {
+            // 可选的分组没有参与匹配时没有值
+            let Some(group_value) = captures.name(group_name) else {
+                continue;
+            };
+            // 记录敏感字段在整个内容中的位置及替换后的内容
+            if let Some(strategy) = parser.mask_strategy(group_name)
+                && !group_value.is_empty()
+            {
+                let replacement = resolver
+                    .masker()
+                    .mask_in_content(strategy, group_value.as_str());
+                let start = event.range.start + group_value.start();
+                masked.push((start..start + group_value.len(), replacement));
+            }
+            if parser.field(group_name).is_none() {
+                // 按字符串输出不会失败
+                let _ = insert_field(
+                    resolver,
+                    parser,
+                    &mut log,
+                    group_name,
+                    group_value.as_str(),
+                    &FieldType::String,
+                );
+            }
+        }
+        // 配置了的字段缺失或为空时使用 default_val，保证输出的字段稳定
+        for (group_name, log_parser_field) in &parser.fields {
+            let value = captures
+                .name(group_name)
+                .map(|m| m.as_str())
+                .filter(|v| !v.is_empty())
+                .or(log_parser_field.default_val.as_deref());
+            let Some(value) = value else {
+                continue;
+            };
+            let result = match parser.field_type(group_name) {
+                Some(FieldType::Date) => parser
+                    .date_format(group_name)
+                    .ok_or_else(|| anyhow!("no valid format_pattern"))
+                    .and_then(|format| format.parse(value, resolver.datetime_zone()))
+                    .map(|date_time| log.date_time = date_time.into()),
+                Some(field_type) => {
+                    insert_field(resolver, parser, &mut log, group_name, value, field_type)
+                }
+                // 不支持的类型在加载规则时已经告警
+                None => continue,
+            };
+            if let Err(error) = result {
+                // 错误信息中带有原值，敏感字段不输出
+                let source = match parser.mask_strategy(group_name) {
+                    Some(_) => "sensitive value cannot be parsed".into(),
+                    None => format!("{error:#}").into(),
+                };
+                let error = ParseError::FieldConversion {
+                    field: group_name.clone(),
+                    log_parser_field_id: log_parser_field.id,
+                    source,
+                };
+                log::warn!("{error}");
+                log.errors.push(error);
+            }
+        }
+        logs.push((event.range.clone(), log));
+    }
+    log::info!("{:?}", subsys_log_parser_config);
+    (logs, last_event)
+}
+
+/// 把捕获到的字段转换类型后放入 attr，敏感字段脱敏后按字符串输出
+fn insert_field(
+    resolver: &Resolver,
+    parser: &CompiledParser,
+    log: &mut Log,
+    group_name: &str,
+    value: &str,
+    field_type: &FieldType,
+) -> anyhow::Result<()> {
+    let value = match parser.mask_strategy(group_name) {
+        Some(strategy) => resolver.masker().mask(strategy, value).map(Value::from),
+        None => Some(field_type.convert(value)?),
+    };
+    if let Some(value) = value {
+        log.log_header.attr.insert(group_name.to_string(), value);
+    }
+    Ok(())
+}
+
+/// 合并重叠的脱敏位置，重叠且替换内容不同时整段完全脱敏
+fn merge_masked(mut ranges: Vec<(Range<usize>, String)>) -> Vec<(Range<usize>, String)> {
+    ranges.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut merged: Vec<(Range<usize>, String)> = Vec::new();
+    for (range, replacement) in ranges {
+        match merged.last_mut() {
+            Some((last, last_replacement)) if range.start < last.end => {
+                if *last != range || *last_replacement != replacement {
+                    last.end = last.end.max(range.end);
+                    *last_replacement = REDACTED.to_string();
+                }
+            }
+            _ => merged.push((range, replacement)),
+        }
+    }
+    merged
+}
+
+/// 替换事件原文中的敏感内容，没有敏感内容时返回 None；
+/// 只有一部分落在事件中的位置按完全脱敏处理
+fn mask_event(
+    content: &str,
+    event: &Range<usize>,
+    masked: &[(Range<usize>, String)],
+) -> Option<String> {
+    let mut out = String::with_capacity(event.len());
+    let mut position = event.start;
+    let mut changed = false;
+    for (range, replacement) in masked {
+        if range.end <= event.start || range.start >= event.end {
+            continue;
+        }
+        let start = range.start.max(event.start);
+        let end = range.end.min(event.end);
+        out.push_str(&content[position..start]);
+        if (start..end) == *range {
+            out.push_str(replacement);
+        } else {
+            out.push_str(REDACTED);
+        }
+        position = end;
+        changed = true;
+    }
+    if !changed {
+        return None;
+    }
+    out.push_str(&content[position..event.end]);
+    Some(out)
+}
+
+/// 开启了 use_header_pattern 且头部带有 pattern 时使用头部的 pattern 切分，
+/// 否则返回 None，使用 log_split
+fn header_splitter(
+    log_header: &LogHeader,
+    subsys_log_parser_config: &SubsysLogParser,
+) -> Option<Arc<Splitter>> {
+    if !subsys_log_parser_config.use_header_pattern {
+        return None;
+    }
+    let pattern = log_header
+        .attr
+        .get("pattern")
+        .and_then(Value::as_str)
+        .filter(|p| !p.is_empty())?;
+    Splitter::cached(pattern)
+        .inspect_err(|error| log::warn!("invalid header pattern {pattern:?}: {error}"))
+        .ok()
+}
+
+/// 取 Cow 的一部分，借用的内容继续借用
+fn sub_cow<'a>(cow: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    match cow {
+        Cow::Borrowed(s) => Cow::Borrowed(&s[range]),
+        Cow::Owned(s) => Cow::Owned(s[range].to_string()),
+    }
+}
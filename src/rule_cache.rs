@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use regex::Regex;
+
+use crate::datetime::DateFormat;
+use crate::error::ParseError;
+use crate::mask::MaskStrategy;
+use crate::models::*;
+use crate::repository::{self, RuleRepository, RuleRows};
+use crate::split::Splitter;
+use crate::validate;
+use crate::value::FieldType;
+
+/// 编译好的 log_parser_pattern
+#[derive(Debug)]
+pub struct CompiledPattern {
+    pub pattern: LogParserPattern,
+    pub regex: Regex,
+}
+
+/// 一条 subsys_log_parser 及其关联的规则、pattern 和字段
+#[derive(Debug)]
+pub struct CompiledParser {
+    pub config: SubsysLogParser,
+    pub rule: LogParserRule,
+    pub file_name: Option<Regex>,
+    pub splitter: Splitter,
+    pub patterns: Vec<CompiledPattern>,
+    /// 以 name_in_capture 为键
+    pub fields: HashMap<String, LogParserField>,
+    /// 字段类型，以 name_in_capture 为键，不支持的类型不在其中
+    pub field_types: HashMap<String, FieldType>,
+    /// 日期字段解析好的格式，以 name_in_capture 为键
+    pub date_formats: HashMap<String, DateFormat>,
+    /// 敏感字段的脱敏方式，以 name_in_capture 为键
+    pub mask_strategies: HashMap<String, MaskStrategy>,
+}
+
+impl CompiledParser {
+    /// file_name 为空时匹配所有文件，否则与文件名或路径之一匹配即可
+    pub fn matches_file(&self, filename: Option<&str>, path: Option<&str>) -> bool {
+        match &self.file_name {
+            None => true,
+            Some(regex) => filename
+                .into_iter()
+                .chain(path)
+                .any(|candidate| regex.is_match(candidate)),
+        }
+    }
+
+    pub fn field(&self, name_in_capture: &str) -> Option<&LogParserField> {
+        self.fields.get(name_in_capture)
+    }
+
+    pub fn field_type(&self, name_in_capture: &str) -> Option<&FieldType> {
+        self.field_types.get(name_in_capture)
+    }
+
+    pub fn date_format(&self, name_in_capture: &str) -> Option<&DateFormat> {
+        self.date_formats.get(name_in_capture)
+    }
+
+    pub fn mask_strategy(&self, name_in_capture: &str) -> Option<&MaskStrategy> {
+        self.mask_strategies.get(name_in_capture)
+    }
+}
+
+/// 某一时刻数据库中全部规则的快照
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    subsystems: HashMap<String, SysSubsysConfig>,
+    parsers: HashMap<String, Vec<Arc<CompiledParser>>>,
+    /// 所有行内容的哈希，用于判断规则是否发生了变化
+    version: u64,
+}
+
+impl RuleSet {
+    pub fn load(repository: &mut dyn RuleRepository) -> anyhow::Result<Self> {
+        Ok(Self::from_rows(repository.load_all()?))
+    }
+
+    /// 由各个表的全部行构建规则
+    pub fn from_rows(rows: RuleRows) -> Self {
+        let version = fingerprint(&rows);
+        Self::build(
+            rows.sys_subsys_config,
+            rows.subsys_log_parser,
+            rows.log_parser_rule,
+            rows.log_parser_pattern,
+            rows.log_parser_field,
+            version,
+        )
+    }
+
+    fn build(
+        subsystems: Vec<SysSubsysConfig>,
+        configs: Vec<SubsysLogParser>,
+        rules: Vec<LogParserRule>,
+        patterns: Vec<LogParserPattern>,
+        fields: Vec<LogParserField>,
+        version: u64,
+    ) -> Self {
+        let rules: HashMap<u64, LogParserRule> = rules.into_iter().map(|r| (r.id, r)).collect();
+        let mut patterns_by_rule: HashMap<u64, Vec<LogParserPattern>> = HashMap::new();
+        for pattern in patterns {
+            patterns_by_rule
+                .entry(pattern.log_parser_rule_id)
+                .or_default()
+                .push(pattern);
+        }
+        let mut fields_by_rule: HashMap<u64, HashMap<String, LogParserField>> = HashMap::new();
+        for field in fields {
+            fields_by_rule
+                .entry(field.log_parser_rule_id)
+                .or_default()
+                .entry(field.name_in_capture.clone())
+                .or_insert(field);
+        }
+
+        let mut parsers: HashMap<String, Vec<Arc<CompiledParser>>> = HashMap::new();
+        for config in configs {
+            let Some(rule) = rules.get(&config.log_parser_rule_id).filter(|r| r.status) else {
+                log::warn!(
+                    "subsys_log_parser {} refers to missing or disabled rule {}",
+                    config.id,
+                    config.log_parser_rule_id
+                );
+                continue;
+            };
+            if let Some(parser) = compile_parser(
+                config,
+                rule.clone(),
+                patterns_by_rule
+                    .get(&rule.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+                fields_by_rule.get(&rule.id).cloned().unwrap_or_default(),
+            ) {
+                parsers
+                    .entry(parser.config.subsys_code.clone())
+                    .or_default()
+                    .push(Arc::new(parser));
+            }
+        }
+
+        Self {
+            subsystems: subsystems
+                .into_iter()
+                .map(|s| (s.subsys_code.clone(), s))
+                .collect(),
+            parsers,
+            version,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn subsys(&self, subsys_code: &str) -> Option<&SysSubsysConfig> {
+        self.subsystems.get(subsys_code)
+    }
+
+    /// 该子系统下 file_name 与文件名或路径匹配的解析配置
+    pub fn parsers_for(
+        &self,
+        subsys_code: &str,
+        filename: Option<&str>,
+        path: Option<&str>,
+    ) -> Vec<Arc<CompiledParser>> {
+        self.parsers
+            .get(subsys_code)
+            .into_iter()
+            .flatten()
+            .filter(|parser| parser.matches_file(filename, path))
+            .cloned()
+            .collect()
+    }
+}
+
+/// 编译一条 subsys_log_parser，file_name/log_split 无法编译时跳过整条配置，
+/// 无法编译的 pattern 单独跳过
+fn compile_parser(
+    config: SubsysLogParser,
+    rule: LogParserRule,
+    patterns: &[LogParserPattern],
+    fields: HashMap<String, LogParserField>,
+) -> Option<CompiledParser> {
+    let file_name = match config.file_name.as_deref().filter(|f| !f.trim().is_empty()) {
+        None => None,
+        Some(file_name) => match Regex::new(file_name) {
+            Ok(regex) => Some(regex),
+            Err(error) => {
+                log::warn!(
+                    "invalid file_name of subsys_log_parser {}: {error}",
+                    config.id
+                );
+                return None;
+            }
+        },
+    };
+    let splitter = match Splitter::new(config.log_split.as_deref()) {
+        Ok(splitter) => splitter,
+        Err(error) => {
+            log::warn!(
+                "invalid log_split of subsys_log_parser {}: {error}",
+                config.id
+            );
+            return None;
+        }
+    };
+    let patterns = patterns
+        .iter()
+        .filter_map(
+            |pattern| match Regex::new(pattern.pattern.as_deref().unwrap_or_default()) {
+                Ok(regex) => Some(CompiledPattern {
+                    pattern: pattern.clone(),
+                    regex,
+                }),
+                Err(error) => {
+                    log::warn!("invalid log_parser_pattern {}: {error}", pattern.id);
+                    None
+                }
+            },
+        )
+        .collect();
+    let field_types = fields
+        .values()
+        .filter_map(|field| match FieldType::from_field(field) {
+            Ok(field_type) => Some((field.name_in_capture.clone(), field_type)),
+            Err(error) => {
+                log::warn!("invalid log_parser_field {}: {error}", field.id);
+                None
+            }
+        })
+        .collect();
+    let date_formats = fields
+        .values()
+        .filter(|field| field.type_ == FieldType::DATE)
+        .filter_map(|field| {
+            let format_pattern = field.format_pattern.as_deref().unwrap_or_default();
+            match DateFormat::from_format_pattern(format_pattern) {
+                Ok(format) => Some((field.name_in_capture.clone(), format)),
+                Err(error) => {
+                    log::warn!(
+                        "invalid format_pattern of log_parser_field {}: {error}",
+                        field.id
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+    let mask_strategies = fields
+        .values()
+        .filter_map(|field| {
+            let strategy = MaskStrategy::from_field(field).unwrap_or_else(|error| {
+                // 配置错误时宁可完全脱敏也不要原样输出
+                log::warn!(
+                    "invalid mask_strategy of log_parser_field {}: {error}",
+                    field.id
+                );
+                Some(MaskStrategy::Redact)
+            })?;
+            Some((field.name_in_capture.clone(), strategy))
+        })
+        .collect();
+    Some(CompiledParser {
+        config,
+        rule,
+        file_name,
+        splitter,
+        patterns,
+        fields,
+        field_types,
+        date_formats,
+        mask_strategies,
+    })
+}
+
+fn fingerprint<T: serde::Serialize>(rows: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(rows)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 内存中的规则缓存
+///
+/// 解析时只读取内存中的快照，刷新时整体替换快照，
+/// 数据库不可用时继续使用旧的规则
+pub struct RuleCache {
+    current: RwLock<Arc<RuleSet>>,
+    /// 规则有错误或警告时拒绝加载
+    strict: bool,
+}
+
+impl RuleCache {
+    /// 加载时检查规则，`strict` 时规则有错误或警告则返回错误
+    pub fn load(repository: &mut dyn RuleRepository, strict: bool) -> anyhow::Result<Self> {
+        let rows = repository
+            .load_all()
+            .map_err(ParseError::repository)
+            .context("failed to load rules")?;
+        validate::check(&rows, strict).context("invalid rules")?;
+        let rules = RuleSet::from_rows(rows);
+        log::info!("loaded rules, version {:x}", rules.version());
+        Ok(Self {
+            current: RwLock::new(Arc::new(rules)),
+            strict,
+        })
+    }
+
+    /// 当前的规则快照
+    pub fn get(&self) -> Arc<RuleSet> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// 重新加载规则，规则有变化时返回 true，strict 模式下有错误或警告的规则不会生效
+    pub fn refresh(&self, repository: &mut dyn RuleRepository) -> anyhow::Result<bool> {
+        let rows = repository
+            .load_all()
+            .map_err(ParseError::repository)
+            .context("failed to reload rules")?;
+        if fingerprint(&rows) == self.get().version() {
+            return Ok(false);
+        }
+        validate::check(&rows, self.strict).context("keeping previous rules")?;
+        let rules = RuleSet::from_rows(rows);
+        log::info!("rules changed, new version {:x}", rules.version());
+        *self.current.write().unwrap() = Arc::new(rules);
+        Ok(true)
+    }
+
+    /// 启动后台线程，每隔 `interval` 重新加载一次规则，直到 `shutdown` 被置为 true
+    pub fn spawn_refresher(
+        self: &Arc<Self>,
+        database_url: String,
+        interval: Duration,
+        shutdown: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        let cache = self.clone();
+        thread::spawn(move || {
+            let mut conn: Option<Box<dyn RuleRepository>> = None;
+            let mut last_refresh = Instant::now();
+            while !shutdown.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(200));
+                if last_refresh.elapsed() < interval {
+                    continue;
+                }
+                last_refresh = Instant::now();
+                if conn.is_none() {
+                    conn = repository::connect(&database_url)
+                        .inspect_err(|error| log::error!("{error:#}"))
+                        .ok();
+                }
+                cache.refresh_with(&mut conn);
+            }
+        })
+    }
+
+    /// 读取失败时丢弃连接，下次重新建立；规则无法通过检查时保留连接和旧的规则
+    fn refresh_with(&self, conn: &mut Option<Box<dyn RuleRepository>>) {
+        let Some(repository) = conn.as_mut() else {
+            return;
+        };
+        if let Err(error) = self.refresh(repository.as_mut()) {
+            log::error!("{error:#}");
+            if ParseError::is_repository_unavailable(&error) {
+                *conn = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+
+    use crate::repository::{MemoryRuleRepository, SqliteRuleRepository};
+
+    fn rows(file_names: &[&str]) -> RuleRows {
+        let mut rows: RuleRows = serde_yaml::from_str(
+            r#"
+sys_subsys_config:
+- {id: 1, sys_code: SYS, subsys_code: APP}
+log_parser_rule:
+- {id: 1, status: true}
+log_parser_pattern:
+- {id: 1, log_parser_rule_id: 1, pattern: '^(?P<message>.*)$'}
+log_parser_field:
+- {id: 1, log_parser_rule_id: 1, name_in_capture: message, type: 0}
+"#,
+        )
+        .unwrap();
+        for (i, file_name) in file_names.iter().enumerate() {
+            rows.subsys_log_parser.push(SubsysLogParser {
+                id: i as u64 + 1,
+                subsys_code: "APP".to_string(),
+                log_parser_rule_id: 1,
+                file_name: Some(file_name.to_string()),
+                status: true,
+                log_split: None,
+                source_topic: "out".to_string(),
+                use_header_pattern: false,
+            });
+        }
+        rows
+    }
+
+    fn rule_set(file_names: &[&str]) -> RuleSet {
+        RuleSet::from_rows(rows(file_names))
+    }
+
+    fn parser_ids(rules: &RuleSet, filename: Option<&str>, path: Option<&str>) -> Vec<u64> {
+        rules
+            .parsers_for("APP", filename, path)
+            .iter()
+            .map(|p| p.config.id)
+            .collect()
+    }
+
+    #[test]
+    fn parsers_match_file_name_against_filename_or_path() {
+        let rules = rule_set(&["", r"^access\.log$", "^/var/log/app/"]);
+        assert_eq!(parser_ids(&rules, None, None), vec![1]);
+        assert_eq!(parser_ids(&rules, Some("access.log"), None), vec![1, 2]);
+        assert_eq!(
+            parser_ids(&rules, Some("error.log"), Some("/var/log/app/error.log")),
+            vec![1, 3]
+        );
+        assert!(rules.parsers_for("OTHER", None, None).is_empty());
+    }
+
+    #[test]
+    fn parser_with_invalid_file_name_is_skipped() {
+        let rules = rule_set(&["(", "log"]);
+        assert_eq!(parser_ids(&rules, Some("a.log"), None), vec![2]);
+    }
+
+    #[test]
+    fn refresh_replaces_the_snapshot_only_when_rules_change() {
+        let mut repository = MemoryRuleRepository::new(rows(&[""]));
+        let cache = RuleCache::load(&mut repository, true).unwrap();
+        let loaded = cache.get();
+        assert!(!cache.refresh(&mut repository).unwrap());
+        assert!(Arc::ptr_eq(&loaded, &cache.get()));
+
+        repository.replace_all(&rows(&["", "log"])).unwrap();
+        assert!(cache.refresh(&mut repository).unwrap());
+        assert_ne!(cache.get().version(), loaded.version());
+        assert_eq!(cache.get().parsers_for("APP", Some("a.log"), None).len(), 2);
+        // 已经取出的快照不受影响
+        assert_eq!(loaded.parsers_for("APP", Some("a.log"), None).len(), 1);
+    }
+
+    #[test]
+    fn strict_cache_keeps_previous_rules_when_new_rules_have_issues() {
+        let mut repository = MemoryRuleRepository::new(rows(&["("]));
+        assert!(RuleCache::load(&mut repository, true).is_err());
+
+        let mut repository = MemoryRuleRepository::new(rows(&[""]));
+        let cache = RuleCache::load(&mut repository, true).unwrap();
+        let version = cache.get().version();
+        repository.replace_all(&rows(&["", "("])).unwrap();
+        assert!(cache.refresh(&mut repository).is_err());
+        assert_eq!(cache.get().version(), version);
+
+        let cache = RuleCache::load(&mut repository, false).unwrap();
+        assert_eq!(cache.get().parsers_for("APP", None, None).len(), 1);
+
+        // 警告同样拒绝
+        let mut rows = rows(&[""]);
+        rows.log_parser_field.clear();
+        repository.replace_all(&rows).unwrap();
+        assert!(RuleCache::load(&mut repository, true).is_err());
+        assert!(RuleCache::load(&mut repository, false).is_ok());
+    }
+
+    #[test]
+    fn refresher_keeps_connection_when_new_rules_are_rejected() {
+        let mut repository = MemoryRuleRepository::new(rows(&[""]));
+        let cache = RuleCache::load(&mut repository, true).unwrap();
+        let version = cache.get().version();
+        repository.replace_all(&rows(&["", "("])).unwrap();
+        let mut conn: Option<Box<dyn RuleRepository>> = Some(Box::new(repository));
+        cache.refresh_with(&mut conn);
+        assert!(conn.is_some());
+        assert_eq!(cache.get().version(), version);
+
+        conn.as_mut()
+            .unwrap()
+            .replace_all(&rows(&["", "log"]))
+            .unwrap();
+        cache.refresh_with(&mut conn);
+        assert!(conn.is_some());
+        assert_ne!(cache.get().version(), version);
+    }
+
+    #[test]
+    fn refresher_drops_connection_when_repository_fails() {
+        let mut repository = SqliteRuleRepository::establish(":memory:").unwrap();
+        repository.replace_all(&rows(&[""])).unwrap();
+        let cache = RuleCache::load(&mut repository, true).unwrap();
+        let version = cache.get().version();
+        repository
+            .connection()
+            .batch_execute("DROP TABLE log_parser_field")
+            .unwrap();
+        let error = cache.refresh(&mut repository).unwrap_err();
+        assert!(ParseError::is_repository_unavailable(&error));
+
+        let mut conn: Option<Box<dyn RuleRepository>> = Some(Box::new(repository));
+        cache.refresh_with(&mut conn);
+        assert!(conn.is_none());
+        assert_eq!(cache.get().version(), version);
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, anyhow};
+
+use crate::repository::RuleRows;
+
+/// 规则文件的格式，由扩展名决定
+///
+/// 文件的顶层键为表名，值为该表的行，字段与 `models` 中的结构一一对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleFileFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl RuleFileFormat {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "yaml" | "yml" => Ok(RuleFileFormat::Yaml),
+            "toml" => Ok(RuleFileFormat::Toml),
+            "json" => Ok(RuleFileFormat::Json),
+            _ => Err(anyhow!(
+                "unsupported rule file {}, expected .yaml, .yml, .toml or .json",
+                path.display()
+            )),
+        }
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> anyhow::Result<RuleRows> {
+    let path = path.as_ref();
+    let format = RuleFileFormat::from_path(path)?;
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let rows = match format {
+        RuleFileFormat::Yaml => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+        RuleFileFormat::Toml => toml::from_str(&content).map_err(anyhow::Error::from),
+        RuleFileFormat::Json => serde_json::from_str(&content).map_err(anyhow::Error::from),
+    };
+    rows.with_context(|| format!("invalid rule file {}", path.display()))
+}
+
+pub fn write(path: impl AsRef<Path>, rows: &RuleRows) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let content = match RuleFileFormat::from_path(path)? {
+        RuleFileFormat::Yaml => serde_yaml::to_string(rows)?,
+        RuleFileFormat::Toml => toml::to_string_pretty(rows)?,
+        RuleFileFormat::Json => serde_json::to_string_pretty(rows)?,
+    };
+    fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("log-resolver-{}-{name}", std::process::id()))
+    }
+
+    fn json(rows: &RuleRows) -> serde_json::Value {
+        serde_json::to_value(rows).unwrap()
+    }
+
+    #[test]
+    fn export_then_import_keeps_every_row() {
+        let rows = read(concat!(env!("CARGO_MANIFEST_DIR"), "/rules/default.yaml")).unwrap();
+        assert_eq!(rows.log_parser_field.len(), 2);
+        for name in ["rules.yaml", "rules.toml", "rules.json"] {
+            let path = temp_path(name);
+            write(&path, &rows).unwrap();
+            let imported = read(&path);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(json(&imported.unwrap()), json(&rows), "{name}");
+        }
+    }
+
+    #[test]
+    fn format_follows_extension() {
+        assert_eq!(
+            RuleFileFormat::from_path(Path::new("a.YML")).unwrap(),
+            RuleFileFormat::Yaml
+        );
+        assert!(RuleFileFormat::from_path(Path::new("a.txt")).is_err());
+        assert!(RuleFileFormat::from_path(Path::new("rules")).is_err());
+    }
+
+    #[test]
+    fn missing_tables_default_to_empty() {
+        let path = temp_path("partial.json");
+        fs::write(&path, r#"{"log_parser_rule": [{"id": 1, "status": true}]}"#).unwrap();
+        let rows = read(&path);
+        fs::remove_file(&path).unwrap();
+        let rows = rows.unwrap();
+        assert_eq!(rows.log_parser_rule.len(), 1);
+        assert!(rows.subsys_log_parser.is_empty());
+
+        let path = temp_path("invalid.yaml");
+        fs::write(&path, "log_parser_rule: [{id: x}]").unwrap();
+        let error = read(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(format!("{error:#}").contains("invalid rule file"));
+    }
+}
@@ -15,6 +15,8 @@ diesel::table! {
         #[max_length = 1024]
         default_val -> Nullable<Varchar>,
         is_sensitive -> Nullable<Bool>,
+        #[max_length = 255]
+        mask_strategy -> Nullable<Varchar>,
     }
 }
 
@@ -52,6 +54,7 @@ diesel::table! {
         log_split -> Nullable<Varchar>,
         #[max_length = 255]
         source_topic -> Varchar,
+        use_header_pattern -> Bool,
     }
 }
 
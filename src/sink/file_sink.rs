@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use super::{LogSink, OutputMessage};
+
+/// 写到目录下以 topic 命名的 jsonl 文件中，每条消息一行
+pub struct FileSink {
+    dir: PathBuf,
+    files: HashMap<String, BufWriter<File>>,
+}
+
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            files: HashMap::new(),
+        })
+    }
+
+    fn writer(&mut self, topic: &str) -> anyhow::Result<&mut BufWriter<File>> {
+        if !self.files.contains_key(topic) {
+            let path = self.dir.join(format!("{topic}.jsonl"));
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            self.files.insert(topic.to_string(), BufWriter::new(file));
+        }
+        Ok(self.files.get_mut(topic).unwrap())
+    }
+}
+
+impl LogSink for FileSink {
+    fn send_batch(&mut self, batch: &[OutputMessage]) -> anyhow::Result<Vec<usize>> {
+        for message in batch {
+            let writer = self.writer(&message.topic)?;
+            writer.write_all(&message.payload)?;
+            writer.write_all(b"\n")?;
+        }
+        for writer in self.files.values_mut() {
+            writer.flush()?;
+        }
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use rdkafka::ClientContext;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+
+use super::{LogSink, OutputMessage};
+use crate::configuration::ProducerConfig;
+
+/// 记录投递失败的消息下标
+#[derive(Default)]
+struct DeliveryContext {
+    failed: Mutex<Vec<usize>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, index: usize) {
+        if let Err((error, _)) = delivery_result {
+            log::warn!("failed to deliver message {index}: {error}");
+            self.failed.lock().unwrap().push(index);
+        }
+    }
+}
+
+/// 发往 Kafka，每条消息发到它自己的 topic
+pub struct KafkaSink {
+    producer: BaseProducer<DeliveryContext>,
+    flush_timeout: Duration,
+}
+
+impl KafkaSink {
+    pub fn new(config: &ProducerConfig) -> anyhow::Result<Self> {
+        let mut client_config = ClientConfig::new();
+        for (key, value) in &config.properties {
+            client_config.set(key, value);
+        }
+        let producer = client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("acks", "all")
+            .create_with_context(DeliveryContext::default())
+            .context("failed to create kafka producer")?;
+        Ok(Self {
+            producer,
+            flush_timeout: config.flush_timeout,
+        })
+    }
+}
+
+impl LogSink for KafkaSink {
+    fn send_batch(&mut self, batch: &[OutputMessage]) -> anyhow::Result<Vec<usize>> {
+        self.producer.context().failed.lock().unwrap().clear();
+        let mut failed = Vec::new();
+        for (index, message) in batch.iter().enumerate() {
+            let mut record = BaseRecord::with_opaque_to(&message.topic, index)
+                .payload(message.payload.as_slice());
+            if let Some(key) = &message.key {
+                record = record.key(key.as_str());
+            }
+            loop {
+                match self.producer.send(record) {
+                    Ok(()) => break,
+                    // 本地队列满了，等待一些消息送达后再试
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r)) => {
+                        record = r;
+                        self.producer.poll(Duration::from_millis(100));
+                    }
+                    Err((error, _)) => {
+                        log::warn!("failed to enqueue message {index}: {error}");
+                        failed.push(index);
+                        break;
+                    }
+                }
+            }
+        }
+        // flush 返回时所有消息都已收到投递回执
+        self.producer
+            .flush(self.flush_timeout)
+            .context("timed out waiting for delivery acknowledgement")?;
+        failed.extend(self.producer.context().failed.lock().unwrap().drain(..));
+        Ok(failed)
+    }
+}
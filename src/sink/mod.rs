@@ -0,0 +1,200 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::configuration::{OutputConfig, SinkConfig};
+
+pub mod file_sink;
+pub mod kafka_sink;
+pub mod stdout_sink;
+
+/// 发往输出端的一条消息
+#[derive(Debug, Clone)]
+pub struct OutputMessage {
+    pub topic: String,
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+/// 输出端，Kafka/文件/标准输出都实现这个 trait
+pub trait LogSink {
+    /// 发送一批消息并等待确认
+    ///
+    /// 返回未能送达的消息在 `batch` 中的下标，整批失败时返回 Err
+    fn send_batch(&mut self, batch: &[OutputMessage]) -> anyhow::Result<Vec<usize>>;
+}
+
+/// 带缓冲和重试的输出
+///
+/// 消息先放入缓冲区，攒够 `batch_size` 条或调用 `flush` 时才真正发送，
+/// 发送失败的消息最多重试 `retries` 次
+pub struct BatchedOutput {
+    sink: Box<dyn LogSink>,
+    buffer: Vec<OutputMessage>,
+    batch_size: usize,
+    retries: u32,
+    retry_backoff: Duration,
+    flushed: u64,
+}
+
+impl BatchedOutput {
+    pub fn from_config(config: &OutputConfig) -> anyhow::Result<Self> {
+        let sink: Box<dyn LogSink> = match &config.sink {
+            SinkConfig::Kafka(producer) => Box::new(kafka_sink::KafkaSink::new(producer)?),
+            SinkConfig::File(dir) => Box::new(file_sink::FileSink::new(dir)?),
+            SinkConfig::Stdout => Box::new(stdout_sink::StdoutSink),
+        };
+        Ok(Self::new(
+            sink,
+            config.batch_size,
+            config.retries,
+            config.retry_backoff,
+        ))
+    }
+
+    pub fn new(
+        sink: Box<dyn LogSink>,
+        batch_size: usize,
+        retries: u32,
+        retry_backoff: Duration,
+    ) -> Self {
+        Self {
+            sink,
+            buffer: Vec::with_capacity(batch_size),
+            batch_size: batch_size.max(1),
+            retries,
+            retry_backoff,
+            flushed: 0,
+        }
+    }
+
+    /// 已经发送过的批次数，调用方据此判断缓冲区之前的消息是否都已送达
+    pub fn flushed(&self) -> u64 {
+        self.flushed
+    }
+
+    pub fn push(&mut self, message: OutputMessage) -> anyhow::Result<()> {
+        self.buffer.push(message);
+        if self.buffer.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 发送缓冲区中的所有消息，返回时它们都已被确认
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut pending = std::mem::take(&mut self.buffer);
+        let mut attempt = 0;
+        while !pending.is_empty() {
+            let failed = match self.sink.send_batch(&pending) {
+                Ok(failed) => failed,
+                Err(error) if attempt < self.retries => {
+                    log::warn!("failed to send {} messages: {error:#}", pending.len());
+                    (0..pending.len()).collect()
+                }
+                Err(error) => return Err(error),
+            };
+            if failed.is_empty() {
+                break;
+            }
+            if attempt >= self.retries {
+                anyhow::bail!(
+                    "{} messages not delivered after {} retries",
+                    failed.len(),
+                    self.retries
+                );
+            }
+            attempt += 1;
+            log::warn!(
+                "retrying {} undelivered messages, attempt {attempt}",
+                failed.len()
+            );
+            pending = failed.into_iter().map(|i| pending[i].clone()).collect();
+            thread::sleep(self.retry_backoff * attempt);
+        }
+        self.flushed += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// 记录每批收到的消息，前 `failures` 次发送时第一条消息投递失败
+    struct RecordingSink {
+        batches: Arc<Mutex<Vec<Vec<String>>>>,
+        failures: u32,
+    }
+
+    impl LogSink for RecordingSink {
+        fn send_batch(&mut self, batch: &[OutputMessage]) -> anyhow::Result<Vec<usize>> {
+            let payloads = batch
+                .iter()
+                .map(|m| String::from_utf8(m.payload.clone()).unwrap())
+                .collect();
+            self.batches.lock().unwrap().push(payloads);
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Ok(vec![0]);
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    fn batched(batch_size: usize, failures: u32) -> (BatchedOutput, Arc<Mutex<Vec<Vec<String>>>>) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = RecordingSink {
+            batches: batches.clone(),
+            failures,
+        };
+        let output = BatchedOutput::new(Box::new(sink), batch_size, 2, Duration::ZERO);
+        (output, batches)
+    }
+
+    fn message(payload: &str) -> OutputMessage {
+        OutputMessage {
+            topic: "out".to_string(),
+            key: None,
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn sends_only_when_batch_is_full_or_flushed() {
+        let (mut output, batches) = batched(2, 0);
+        output.push(message("a")).unwrap();
+        assert!(batches.lock().unwrap().is_empty());
+        assert_eq!(output.flushed(), 0);
+        output.push(message("b")).unwrap();
+        output.push(message("c")).unwrap();
+        assert_eq!(output.flushed(), 1);
+        output.flush().unwrap();
+        assert_eq!(output.flushed(), 2);
+        // 缓冲区为空时不发送
+        output.flush().unwrap();
+        assert_eq!(output.flushed(), 2);
+        assert_eq!(*batches.lock().unwrap(), vec![vec!["a", "b"], vec!["c"]]);
+    }
+
+    #[test]
+    fn retries_only_undelivered_messages() {
+        let (mut output, batches) = batched(10, 2);
+        output.push(message("a")).unwrap();
+        output.push(message("b")).unwrap();
+        output.flush().unwrap();
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![vec!["a", "b"], vec!["a"], vec!["a"]]
+        );
+
+        let (mut output, _) = batched(10, 3);
+        output.push(message("a")).unwrap();
+        assert!(output.flush().is_err());
+        assert_eq!(output.flushed(), 0);
+    }
+}
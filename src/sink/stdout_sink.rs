@@ -0,0 +1,20 @@
+use std::io::{self, Write};
+
+use super::{LogSink, OutputMessage};
+
+/// 输出到标准输出，格式为 `topic<TAB>payload`，用于调试
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn send_batch(&mut self, batch: &[OutputMessage]) -> anyhow::Result<Vec<usize>> {
+        let mut stdout = io::stdout().lock();
+        for message in batch {
+            stdout.write_all(message.topic.as_bytes())?;
+            stdout.write_all(b"\t")?;
+            stdout.write_all(&message.payload)?;
+            stdout.write_all(b"\n")?;
+        }
+        stdout.flush()?;
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use encoding_rs::Encoding;
+use fancy_regex::{Regex, RegexBuilder};
+use once_cell::sync::Lazy;
+
+/// 单次匹配最多回溯的步数，防止病态的正则拖垮消费
+const BACKTRACK_LIMIT: usize = 1_000_000;
+/// 编译后的正则最大占用
+const SIZE_LIMIT: usize = 1 << 20;
+/// 头部 pattern 编译缓存的最大条目数，超过后清空
+const CACHE_CAPACITY: usize = 256;
+
+static SPLITTER_CACHE: Lazy<Mutex<HashMap<String, Arc<Splitter>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 从一个日志块中切分出的一条事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// 事件在解码后内容中的范围
+    pub range: Range<usize>,
+    /// 事件在原始(未解码)日志块中的字节偏移
+    pub byte_offset: usize,
+    /// 事件第一行在日志块中的行偏移，从0开始
+    pub line_offset: usize,
+}
+
+/// 按 subsys_log_parser.log_split 或头部的 pattern 切分日志块
+///
+/// 在每一个 match 的开头切分，切分出的事件会去掉首尾的换行符，空事件被丢弃。
+/// 使用回溯引擎，支持 Java 风格的 `(?=...)`/`(?<=...)` 等环视，
+/// 不含这些语法的正则会自动交给 regex crate 执行
+#[derive(Debug, Clone)]
+pub struct Splitter {
+    regex: Option<Regex>,
+}
+
+impl Splitter {
+    /// `log_split` 为空时整个日志块作为一条事件
+    pub fn new(log_split: Option<&str>) -> anyhow::Result<Self> {
+        let regex = match log_split {
+            Some(pattern) if !pattern.is_empty() => Some(
+                RegexBuilder::new(pattern)
+                    .backtrack_limit(BACKTRACK_LIMIT)
+                    .delegate_size_limit(SIZE_LIMIT)
+                    .build()?,
+            ),
+            _ => None,
+        };
+        Ok(Self { regex })
+    }
+
+    /// 和 `new` 一样，但复用已经编译过的结果，用于每条记录都携带的头部 pattern
+    pub fn cached(pattern: &str) -> anyhow::Result<Arc<Self>> {
+        let mut cache = SPLITTER_CACHE.lock().unwrap();
+        if let Some(splitter) = cache.get(pattern) {
+            return Ok(splitter.clone());
+        }
+        let splitter = Arc::new(Self::new(Some(pattern))?);
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), splitter.clone());
+        Ok(splitter)
+    }
+
+    pub fn split(&self, content: &str, encoding: &'static Encoding) -> Vec<Event> {
+        self.split_block(content, encoding).0
+    }
+
+    /// 和 `split` 一样，另外返回最后一个事件是否可能还没有结束
+    ///
+    /// 最后一个事件之后还有切分位置(比如以 log_split 结尾)时，日志块结束在事件边界上
+    pub fn split_block(&self, content: &str, encoding: &'static Encoding) -> (Vec<Event>, bool) {
+        let mut starts = vec![0];
+        if let Some(regex) = &self.regex {
+            for m in regex.find_iter(content) {
+                match m {
+                    Ok(m) if m.start() > 0 => starts.push(m.start()),
+                    Ok(_) => {}
+                    // 超出回溯限制时，剩余部分不再切分
+                    Err(error) => {
+                        log::warn!(
+                            "log split aborted at byte {}: {error}",
+                            starts.last().unwrap()
+                        );
+                        break;
+                    }
+                }
+            }
+            starts.dedup();
+        }
+        let last_start = *starts.last().unwrap();
+        starts.push(content.len());
+
+        let mut offsets = OffsetCounter::new(content, encoding);
+        let events: Vec<_> = starts
+            .windows(2)
+            .filter_map(|w| {
+                let range = trim_line_breaks(content, w[0]..w[1]);
+                if range.is_empty() {
+                    return None;
+                }
+                let (byte_offset, line_offset) = offsets.advance_to(range.start);
+                Some(Event {
+                    range,
+                    byte_offset,
+                    line_offset,
+                })
+            })
+            .collect();
+        let open = events
+            .last()
+            .is_some_and(|event| event.range.start >= last_start);
+        (events, open)
+    }
+}
+
+fn trim_line_breaks(content: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &content[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start_matches(['\r', '\n']).len());
+    let end = range.end - (slice.len() - slice.trim_end_matches(['\r', '\n']).len());
+    start..end.max(start)
+}
+
+/// 递增地计算解码内容中某个位置对应的原始字节偏移和行偏移
+struct OffsetCounter<'a> {
+    content: &'a str,
+    encoding: &'static Encoding,
+    position: usize,
+    byte_offset: usize,
+    line_offset: usize,
+}
+
+impl<'a> OffsetCounter<'a> {
+    fn new(content: &'a str, encoding: &'static Encoding) -> Self {
+        Self {
+            content,
+            encoding,
+            position: 0,
+            byte_offset: 0,
+            line_offset: 0,
+        }
+    }
+
+    fn advance_to(&mut self, position: usize) -> (usize, usize) {
+        let segment = &self.content[self.position..position];
+        self.byte_offset += if self.encoding == encoding_rs::UTF_8 {
+            segment.len()
+        } else {
+            self.encoding.encode(segment).0.len()
+        };
+        self.line_offset += segment.bytes().filter(|&b| b == b'\n').count();
+        self.position = position;
+        (self.byte_offset, self.line_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(log_split: Option<&str>, content: &str) -> Vec<(String, usize, usize)> {
+        Splitter::new(log_split)
+            .unwrap()
+            .split(content, encoding_rs::UTF_8)
+            .into_iter()
+            .map(|e| (content[e.range].to_string(), e.byte_offset, e.line_offset))
+            .collect()
+    }
+
+    #[test]
+    fn without_log_split_the_block_is_one_event() {
+        assert_eq!(split(None, "a\nb\n"), vec![("a\nb".to_string(), 0, 0)]);
+        assert_eq!(split(Some(""), "a\nb"), vec![("a\nb".to_string(), 0, 0)]);
+    }
+
+    #[test]
+    fn splits_at_match_start_and_trims_line_breaks() {
+        let content = "\n2024 a\ncontinued\r\n2024 b\n\n2024 c";
+        assert_eq!(
+            split(Some("2024"), content),
+            vec![
+                ("2024 a\ncontinued".to_string(), 1, 1),
+                ("2024 b".to_string(), 19, 3),
+                ("2024 c".to_string(), 27, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_empty_events() {
+        assert_eq!(
+            split(Some("\n"), "a\n\n\nb\n"),
+            vec![("a".to_string(), 0, 0), ("b".to_string(), 4, 3)]
+        );
+        assert!(split(Some("\n"), "\n\n").is_empty());
+    }
+
+    #[test]
+    fn block_ending_with_log_split_is_complete() {
+        let splitter = Splitter::new(Some("\n")).unwrap();
+        assert!(splitter.split_block("a\nb", encoding_rs::UTF_8).1);
+        assert!(!splitter.split_block("a\nb\n", encoding_rs::UTF_8).1);
+        // 按行首切分时，结尾的换行之后可能还有属于最后一个事件的行
+        let splitter = Splitter::new(Some(r"\n(?=INFO)")).unwrap();
+        assert!(splitter.split_block("INFO a\n", encoding_rs::UTF_8).1);
+        assert!(
+            !Splitter::new(None)
+                .unwrap()
+                .split_block("", encoding_rs::UTF_8)
+                .1
+        );
+    }
+
+    #[test]
+    fn supports_lookahead() {
+        let content = "INFO a\n  at x\nERROR b";
+        assert_eq!(
+            split(Some(r"\n(?=INFO|ERROR)"), content),
+            vec![
+                ("INFO a\n  at x".to_string(), 0, 0),
+                ("ERROR b".to_string(), 14, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_offset_counts_bytes_of_the_original_encoding() {
+        let content = "中文\n2024 b";
+        let events = Splitter::new(Some("2024"))
+            .unwrap()
+            .split(content, encoding_rs::GBK);
+        assert_eq!(events[1].range.start, 7);
+        // GBK 中每个汉字两个字节
+        assert_eq!(events[1].byte_offset, 5);
+        assert_eq!(events[1].line_offset, 1);
+    }
+
+    #[test]
+    fn cached_splitters_are_shared() {
+        let a = Splitter::cached("x+").unwrap();
+        let b = Splitter::cached("x+").unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(Splitter::cached("(").is_err());
+    }
+}
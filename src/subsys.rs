@@ -0,0 +1,230 @@
+use anyhow::{Context, anyhow};
+use regex::Regex;
+
+use crate::dialect::RecordContext;
+use crate::header::{DEFAULT_SUBSYS_KEYS, LogHeader};
+use crate::util::is_empty;
+
+/// 子系统的一个来源
+#[derive(Debug, Clone)]
+pub enum SubsysLookup {
+    /// 记录的 key
+    Key,
+    /// 记录所在的 topic
+    Topic,
+    /// 头部中的值，见 `LogHeader::get`
+    Header(String),
+    /// 用正则从另一个来源中取出，有名为 subsys 的分组时取该分组，其次取第一个分组，否则取整个匹配
+    Regex {
+        from: Box<SubsysLookup>,
+        regex: Regex,
+    },
+}
+
+impl SubsysLookup {
+    /// `key`、`topic`、头部的键，或者 `regex:<来源>:<正则>`
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        if let Some(rest) = s.strip_prefix("regex:") {
+            let (from, pattern) = rest.split_once(':').ok_or_else(|| {
+                anyhow!("invalid subsys lookup {s}, expected regex:<from>:<pattern>")
+            })?;
+            let regex =
+                Regex::new(pattern).with_context(|| format!("invalid subsys regex {pattern}"))?;
+            return Ok(SubsysLookup::Regex {
+                from: Box::new(Self::parse(from)?),
+                regex,
+            });
+        }
+        match s {
+            "" => Err(anyhow!("empty subsys lookup")),
+            "key" => Ok(SubsysLookup::Key),
+            "topic" => Ok(SubsysLookup::Topic),
+            _ => Ok(SubsysLookup::Header(s.to_string())),
+        }
+    }
+
+    /// 取出的值去掉首尾空白后为空或 `null` 时视为没有
+    fn lookup(&self, header: &LogHeader, context: RecordContext) -> Option<String> {
+        let value = match self {
+            SubsysLookup::Key => Some(context.key.to_string()),
+            SubsysLookup::Topic => Some(context.topic.to_string()),
+            SubsysLookup::Header(key) => header.get(key),
+            SubsysLookup::Regex { from, regex } => {
+                let value = from.lookup(header, context)?;
+                let captures = regex.captures(&value)?;
+                captures
+                    .name("subsys")
+                    .or_else(|| captures.get(1))
+                    .or_else(|| captures.get(0))
+                    .map(|m| m.as_str().to_string())
+            }
+        };
+        value.map(|v| v.trim().to_string()).filter(|v| !is_empty(v))
+    }
+}
+
+/// 按顺序尝试的子系统来源，都没有时使用 fallback
+#[derive(Debug, Clone)]
+pub struct SubsysChain {
+    lookups: Vec<SubsysLookup>,
+    fallback: Option<String>,
+}
+
+/// 与头部的默认规则相同，依次取 fields0.SUBSYSCODE、subsyscode，没有 fallback
+impl Default for SubsysChain {
+    fn default() -> Self {
+        Self {
+            lookups: DEFAULT_SUBSYS_KEYS
+                .iter()
+                .map(|key| SubsysLookup::Header(key.to_string()))
+                .collect(),
+            fallback: None,
+        }
+    }
+}
+
+impl SubsysChain {
+    pub fn new(lookups: Vec<SubsysLookup>, fallback: Option<String>) -> Self {
+        Self { lookups, fallback }
+    }
+
+    /// `;` 分隔的来源，例如 `fields0.SUBSYSCODE;subsyscode;regex:path:^/host/applogs/([^/]+)/`，
+    /// `regex:` 之后的内容都属于正则，正则中可以有 `;`，因此只能放在最后；
+    /// `lookups` 为空时使用默认的来源，`fallback` 为空时没有 fallback
+    pub fn parse(lookups: &str, fallback: Option<&str>) -> anyhow::Result<Self> {
+        let mut chain = Self::default();
+        let mut parsed = Vec::new();
+        let mut rest = lookups;
+        while !rest.trim().is_empty() {
+            let (lookup, next) = if rest.trim_start().starts_with("regex:") {
+                (rest, "")
+            } else {
+                rest.split_once(';').unwrap_or((rest, ""))
+            };
+            if !lookup.trim().is_empty() {
+                parsed.push(SubsysLookup::parse(lookup)?);
+            }
+            rest = next;
+        }
+        if !parsed.is_empty() {
+            chain.lookups = parsed;
+        }
+        chain.fallback = fallback
+            .map(str::trim)
+            .filter(|fallback| !fallback.is_empty())
+            .map(str::to_string);
+        Ok(chain)
+    }
+
+    /// 确定记录的子系统，所有来源都没有且没有 fallback 时返回 None
+    pub fn resolve(&self, header: &LogHeader, context: RecordContext) -> Option<String> {
+        self.lookups
+            .iter()
+            .find_map(|lookup| lookup.lookup(header, context))
+            .or_else(|| self.fallback.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(raw: &[u8]) -> LogHeader {
+        LogHeader::from_bytes(raw).unwrap()
+    }
+
+    fn context<'a>(key: &'a str, topic: &'a str) -> RecordContext<'a> {
+        RecordContext { key, topic }
+    }
+
+    #[test]
+    fn default_chain_prefers_fields0_and_skips_null() {
+        let chain = SubsysChain::default();
+        let header_both = header(b"[[subsyscode=B][fields0.SUBSYSCODE=A]]");
+        assert_eq!(
+            chain.resolve(&header_both, context("", "")).as_deref(),
+            Some("A")
+        );
+        let header_null = header(b"[[subsyscode=B][fields0.SUBSYSCODE=null]]");
+        assert_eq!(
+            chain.resolve(&header_null, context("", "")).as_deref(),
+            Some("B")
+        );
+        let header_none = header(b"[[subsyscode=null][fields0.SUBSYSCODE= ]]");
+        assert_eq!(chain.resolve(&header_none, context("key", "topic")), None);
+    }
+
+    #[test]
+    fn falls_back_after_every_lookup() {
+        let chain = SubsysChain::parse("subsyscode;key", Some(" FALLBACK ")).unwrap();
+        let header = header(b"[[subsyscode=null]]");
+        assert_eq!(
+            chain.resolve(&header, context("KEY", "")).as_deref(),
+            Some("KEY")
+        );
+        assert_eq!(
+            chain.resolve(&header, context("", "")).as_deref(),
+            Some("FALLBACK")
+        );
+        // 为空时使用默认的来源，没有 fallback
+        let chain = SubsysChain::parse(" ; ", Some("")).unwrap();
+        assert_eq!(chain.resolve(&header, context("KEY", "TOPIC")), None);
+    }
+
+    #[test]
+    fn derives_subsys_with_regex() {
+        let chain = SubsysChain::parse(
+            "fields0.SUBSYSCODE;regex:path:^/host/applogs/(?P<subsys>[^/;]+)(;|/)",
+            None,
+        )
+        .unwrap();
+        let path_header = header(b"[[path=/host/applogs/openbank/executor.log]]");
+        assert_eq!(
+            chain.resolve(&path_header, context("", "")).as_deref(),
+            Some("openbank")
+        );
+
+        let chain = SubsysChain::parse("regex:key:^[a-z]+-(\\w+)", None).unwrap();
+        let other = header(b"[[subsyscode=A]]");
+        assert_eq!(
+            chain.resolve(&other, context("app-ceu", "")).as_deref(),
+            Some("ceu")
+        );
+        assert_eq!(chain.resolve(&other, context("APP", "")), None);
+
+        let chain = SubsysChain::parse("regex:topic:^log_", Some("F")).unwrap();
+        assert_eq!(
+            chain.resolve(&other, context("", "log_x")).as_deref(),
+            Some("log_")
+        );
+        assert_eq!(
+            chain.resolve(&other, context("", "raw")).as_deref(),
+            Some("F")
+        );
+    }
+
+    #[test]
+    fn padded_null_falls_through_to_fallback() {
+        let chain = SubsysChain::parse("subsyscode;key;regex:topic:^log_(.*)$", Some("F")).unwrap();
+        let padded = header(b"[[subsyscode= null ]]");
+        assert_eq!(
+            chain
+                .resolve(&padded, context(" NULL ", "log_ null "))
+                .as_deref(),
+            Some("F")
+        );
+        assert_eq!(
+            chain.resolve(&padded, context("", "log_ x ")).as_deref(),
+            Some("x")
+        );
+        assert!(is_empty(" null "));
+    }
+
+    #[test]
+    fn rejects_invalid_lookups() {
+        assert!(SubsysChain::parse("regex:path", None).is_err());
+        assert!(SubsysChain::parse("regex:path:(", None).is_err());
+        assert!(SubsysLookup::parse(" ").is_err());
+    }
+}
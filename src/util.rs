@@ -1,5 +1,7 @@
-fn is_empty(s: &str) -> bool {
-    s.trim().len() == 0 || s.to_ascii_lowercase() == "null"
+/// 去掉首尾空白后为空或 `null`
+pub fn is_empty(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.eq_ignore_ascii_case("null")
 }
 
 pub trait IsEmpty<T> {
@@ -8,6 +10,6 @@ pub trait IsEmpty<T> {
 
 impl IsEmpty<String> for String {
     fn is_empty(t: String) -> bool {
-        todo!()
+        is_empty(&t)
     }
 }
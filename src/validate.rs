@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use anyhow::anyhow;
+use regex::Regex;
+
+use crate::datetime::{DateFormat, Zone};
+use crate::mask::MaskStrategy;
+use crate::repository::RuleRows;
+use crate::split::Splitter;
+use crate::value::FieldType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// 规则可以使用，但结果可能不符合预期
+    Warning,
+    /// 规则的一部分会被跳过或无法正确解析
+    Error,
+}
+
+/// 规则中的一个问题，带有所在的表和行 id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub table: &'static str,
+    pub id: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{severity}: {} {}: {}",
+            self.table, self.id, self.message
+        )
+    }
+}
+
+/// 检查全部规则，返回发现的问题，按表和 id 排序
+pub fn validate(rows: &RuleRows) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut issue = |severity, table, id, message: String| {
+        issues.push(Issue {
+            severity,
+            table,
+            id,
+            message,
+        })
+    };
+
+    let subsys_codes: HashSet<&str> = rows
+        .sys_subsys_config
+        .iter()
+        .map(|s| s.subsys_code.as_str())
+        .collect();
+    let rules: HashMap<u64, bool> = rows
+        .log_parser_rule
+        .iter()
+        .map(|r| (r.id, r.status))
+        .collect();
+
+    for config in rows.subsys_log_parser.iter().filter(|c| c.status) {
+        let table = "subsys_log_parser";
+        match rules.get(&config.log_parser_rule_id) {
+            None => issue(
+                Severity::Error,
+                table,
+                config.id,
+                format!("refers to missing rule {}", config.log_parser_rule_id),
+            ),
+            Some(false) => issue(
+                Severity::Error,
+                table,
+                config.id,
+                format!("refers to disabled rule {}", config.log_parser_rule_id),
+            ),
+            Some(true) => {}
+        }
+        if !subsys_codes.contains(config.subsys_code.as_str()) {
+            issue(
+                Severity::Warning,
+                table,
+                config.id,
+                format!(
+                    "subsys_code {} is not in sys_subsys_config",
+                    config.subsys_code
+                ),
+            );
+        }
+        if let Some(file_name) = config.file_name.as_deref().filter(|f| !f.trim().is_empty())
+            && let Err(error) = Regex::new(file_name)
+        {
+            issue(
+                Severity::Error,
+                table,
+                config.id,
+                format!("invalid file_name: {error}"),
+            );
+        }
+        if let Err(error) = Splitter::new(config.log_split.as_deref()) {
+            issue(
+                Severity::Error,
+                table,
+                config.id,
+                format!("invalid log_split: {error}"),
+            );
+        }
+    }
+
+    // 每条规则中所有 pattern 的命名分组
+    let mut groups_by_rule: HashMap<u64, HashMap<String, u64>> = HashMap::new();
+    let mut rules_with_patterns = HashSet::new();
+    for pattern in &rows.log_parser_pattern {
+        let table = "log_parser_pattern";
+        rules_with_patterns.insert(pattern.log_parser_rule_id);
+        if !rules.contains_key(&pattern.log_parser_rule_id) {
+            issue(
+                Severity::Warning,
+                table,
+                pattern.id,
+                format!("refers to missing rule {}", pattern.log_parser_rule_id),
+            );
+        }
+        let Some(source) = pattern.pattern.as_deref().filter(|p| !p.is_empty()) else {
+            issue(
+                Severity::Error,
+                table,
+                pattern.id,
+                "pattern is empty".into(),
+            );
+            continue;
+        };
+        match Regex::new(source) {
+            Ok(regex) => {
+                let groups = groups_by_rule
+                    .entry(pattern.log_parser_rule_id)
+                    .or_default();
+                for name in regex.capture_names().flatten() {
+                    groups.entry(name.to_string()).or_insert(pattern.id);
+                }
+            }
+            Err(error) => issue(
+                Severity::Error,
+                table,
+                pattern.id,
+                format!("pattern does not compile: {error}"),
+            ),
+        }
+    }
+    for rule in rows.log_parser_rule.iter().filter(|r| r.status) {
+        if !rules_with_patterns.contains(&rule.id) {
+            issue(
+                Severity::Warning,
+                "log_parser_rule",
+                rule.id,
+                "rule has no log_parser_pattern".into(),
+            );
+        }
+    }
+
+    let mut fields_by_rule: HashMap<u64, HashSet<&str>> = HashMap::new();
+    for field in &rows.log_parser_field {
+        let table = "log_parser_field";
+        let name = field.name_in_capture.as_str();
+        if !fields_by_rule
+            .entry(field.log_parser_rule_id)
+            .or_default()
+            .insert(name)
+        {
+            issue(
+                Severity::Warning,
+                table,
+                field.id,
+                format!("duplicate name_in_capture {name}, only the first row is used"),
+            );
+        }
+        let has_group = groups_by_rule
+            .get(&field.log_parser_rule_id)
+            .is_some_and(|groups| groups.contains_key(name));
+        if !has_group {
+            issue(
+                Severity::Warning,
+                table,
+                field.id,
+                format!(
+                    "name_in_capture {name} matches no group in rule {}",
+                    field.log_parser_rule_id
+                ),
+            );
+        }
+        let default_val = field.default_val.as_deref();
+        match FieldType::from_field(field) {
+            Ok(FieldType::Date) => {
+                let format_pattern = field.format_pattern.as_deref().unwrap_or_default();
+                let format = if format_pattern.trim().is_empty() {
+                    Err(anyhow!("date field has no format_pattern"))
+                } else {
+                    DateFormat::from_format_pattern(format_pattern)
+                };
+                match format {
+                    Ok(format) => {
+                        if let Some(default_val) = default_val
+                            && let Err(error) = format.parse(default_val, &Zone::default())
+                        {
+                            issue(
+                                Severity::Error,
+                                table,
+                                field.id,
+                                format!("invalid default_val: {error:#}"),
+                            );
+                        }
+                    }
+                    Err(error) => issue(Severity::Error, table, field.id, format!("{error}")),
+                }
+            }
+            Ok(field_type) => {
+                if let Some(default_val) = default_val
+                    && let Err(error) = field_type.convert(default_val)
+                {
+                    issue(
+                        Severity::Error,
+                        table,
+                        field.id,
+                        format!("invalid default_val: {error:#}"),
+                    );
+                }
+            }
+            Err(error) => issue(Severity::Error, table, field.id, format!("{error}")),
+        }
+        if let Err(error) = MaskStrategy::from_field(field) {
+            issue(
+                Severity::Error,
+                table,
+                field.id,
+                format!("{error}, falling back to redact"),
+            );
+        }
+    }
+
+    // 没有对应字段的分组按字符串输出
+    for (rule_id, groups) in &groups_by_rule {
+        let fields = fields_by_rule.get(rule_id);
+        for (name, pattern_id) in groups {
+            if !fields.is_some_and(|fields| fields.contains(name.as_str())) {
+                issue(
+                    Severity::Warning,
+                    "log_parser_pattern",
+                    *pattern_id,
+                    format!("group {name} has no log_parser_field, it is output as a string"),
+                );
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| (a.table, a.id, &a.message).cmp(&(b.table, b.id, &b.message)));
+    issues
+}
+
+/// 导致检查失败的问题数：错误总是计入，`strict` 时警告也计入
+///
+/// `RULES_STRICT` 和 `validate --strict` 都按这个定义
+pub fn failures(issues: &[Issue], strict: bool) -> usize {
+    issues
+        .iter()
+        .filter(|i| strict || i.severity == Severity::Error)
+        .count()
+}
+
+/// 记录全部问题，`strict` 时有错误或警告则返回错误，否则只记录
+pub fn check(rows: &RuleRows, strict: bool) -> anyhow::Result<()> {
+    let issues = validate(rows);
+    for issue in &issues {
+        match issue.severity {
+            Severity::Warning => log::warn!("{issue}"),
+            Severity::Error => log::error!("{issue}"),
+        }
+    }
+    let failures = failures(&issues, true);
+    if strict && failures > 0 {
+        return Err(anyhow!("rules have {failures} error(s) or warning(s)"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+sys_subsys_config:
+- {id: 1, sys_code: SYS, subsys_code: APP}
+subsys_log_parser:
+- {id: 1, subsys_code: APP, log_parser_rule_id: 1, status: true, source_topic: out}
+log_parser_rule:
+- {id: 1, status: true}
+log_parser_pattern:
+- {id: 1, log_parser_rule_id: 1, pattern: '^(?P<time>\S+) (?P<message>.*)$'}
+log_parser_field:
+- {id: 1, log_parser_rule_id: 1, name_in_capture: time, type: 10, format_pattern: 'yyyy-MM-dd'}
+- {id: 2, log_parser_rule_id: 1, name_in_capture: message, type: 0}
+"#;
+
+    fn rows() -> RuleRows {
+        serde_yaml::from_str(RULES).unwrap()
+    }
+
+    fn messages(rows: &RuleRows) -> Vec<String> {
+        validate(rows).iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn valid_rules_have_no_issues() {
+        assert!(validate(&rows()).is_empty());
+        assert!(check(&rows(), true).is_ok());
+    }
+
+    #[test]
+    fn reports_broken_references_and_regexes() {
+        let mut rows = rows();
+        rows.subsys_log_parser[0].log_parser_rule_id = 9;
+        rows.subsys_log_parser[0].subsys_code = "OTHER".to_string();
+        rows.subsys_log_parser[0].file_name = Some("(".to_string());
+        rows.subsys_log_parser[0].log_split = Some("[".to_string());
+        rows.log_parser_pattern[0].pattern = Some("(?P<time>".to_string());
+        let messages = messages(&rows);
+        assert_eq!(messages.len(), 7, "{messages:#?}");
+        assert!(
+            messages
+                .iter()
+                .any(|m| m == "error: subsys_log_parser 1: refers to missing rule 9")
+        );
+        assert!(messages.iter().any(|m| m.contains("invalid file_name")));
+        assert!(messages.iter().any(|m| m.contains("invalid log_split")));
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.contains("not in sys_subsys_config"))
+        );
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.contains("pattern does not compile"))
+        );
+        // pattern 无法编译时字段也找不到分组
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.contains("name_in_capture time matches no group"))
+        );
+    }
+
+    #[test]
+    fn reports_invalid_fields() {
+        let mut rows = rows();
+        rows.log_parser_field[0].format_pattern = None;
+        rows.log_parser_field[1].type_ = 1;
+        rows.log_parser_field[1].default_val = Some("abc".to_string());
+        rows.log_parser_field[1].is_sensitive = Some(true);
+        rows.log_parser_field[1].mask_strategy = Some("hash".to_string());
+        assert_eq!(
+            messages(&rows),
+            vec![
+                "error: log_parser_field 1: date field has no format_pattern",
+                "error: log_parser_field 2: invalid default_val: \"abc\" is not an integer: \
+                 invalid digit found in string",
+                "error: log_parser_field 2: unknown mask strategy \"hash\", falling back to redact",
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_also_fails_on_warnings() {
+        let mut rows = rows();
+        rows.log_parser_field.pop();
+        let issues = validate(&rows);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(failures(&issues, false), 0);
+        assert_eq!(failures(&issues, true), 1);
+        assert!(check(&rows, false).is_ok());
+        assert!(check(&rows, true).is_err());
+
+        rows.log_parser_pattern[0].pattern = None;
+        let issues = validate(&rows);
+        assert_eq!(failures(&issues, false), 1);
+        assert!(check(&rows, false).is_ok());
+    }
+}
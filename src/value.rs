@@ -0,0 +1,308 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, anyhow};
+use serde::Serialize;
+
+use crate::models::LogParserField;
+
+/// log_parser_field.type 表示的字段类型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    /// 0，原样输出
+    String,
+    /// 1，64位有符号整数
+    Integer,
+    /// 2，浮点数，不接受 NaN 和无穷大
+    Float,
+    /// 3，`true/false`、`yes/no`、`on/off`、`1/0`，不区分大小写
+    Boolean,
+    /// 4，例如 `123ms`、`1.5s`、`1h30m`，不带单位时按 format_pattern 中的单位，默认毫秒
+    Duration { default_unit: Duration },
+    /// 5，IPv4 或 IPv6 地址
+    Ip,
+    /// 6，枚举，例如日志级别。format_pattern 为逗号分隔的可选值，
+    /// 不区分大小写地匹配后输出配置中的写法；未配置时转为大写
+    Enum { values: Vec<String> },
+    /// 7，嵌入的 JSON
+    Json,
+    /// 10，日期，用于 Log 的时间
+    Date,
+}
+
+impl FieldType {
+    pub const STRING: i32 = 0;
+    pub const INTEGER: i32 = 1;
+    pub const FLOAT: i32 = 2;
+    pub const BOOLEAN: i32 = 3;
+    pub const DURATION: i32 = 4;
+    pub const IP: i32 = 5;
+    pub const ENUM: i32 = 6;
+    pub const JSON: i32 = 7;
+    pub const DATE: i32 = 10;
+
+    pub fn from_field(field: &LogParserField) -> anyhow::Result<Self> {
+        let format_pattern = field
+            .format_pattern
+            .as_deref()
+            .map(str::trim)
+            .filter(|f| !f.is_empty());
+        let field_type = match field.type_ {
+            Self::STRING => FieldType::String,
+            Self::INTEGER => FieldType::Integer,
+            Self::FLOAT => FieldType::Float,
+            Self::BOOLEAN => FieldType::Boolean,
+            Self::DURATION => FieldType::Duration {
+                default_unit: match format_pattern {
+                    Some(unit) => duration_unit(unit)
+                        .ok_or_else(|| anyhow!("unknown duration unit {unit:?}"))?,
+                    None => Duration::from_millis(1),
+                },
+            },
+            Self::IP => FieldType::Ip,
+            Self::ENUM => FieldType::Enum {
+                values: format_pattern
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            },
+            Self::JSON => FieldType::Json,
+            Self::DATE => FieldType::Date,
+            other => return Err(anyhow!("unsupported field type {other}")),
+        };
+        Ok(field_type)
+    }
+
+    /// 把捕获到的文本转换为该类型的值，日期字段由调用方按 DateFormat 处理
+    pub fn convert(&self, value: &str) -> anyhow::Result<Value> {
+        let trimmed = value.trim();
+        let converted = match self {
+            FieldType::String | FieldType::Date => Value::String(value.to_string()),
+            FieldType::Integer => Value::Integer(
+                trimmed
+                    .parse()
+                    .with_context(|| format!("{value:?} is not an integer"))?,
+            ),
+            FieldType::Float => {
+                let float: f64 = trimmed
+                    .parse()
+                    .with_context(|| format!("{value:?} is not a number"))?;
+                if !float.is_finite() {
+                    return Err(anyhow!("{value:?} is not a finite number"));
+                }
+                Value::Float(float)
+            }
+            FieldType::Boolean => Value::Boolean(match trimmed.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "y" | "on" | "1" => true,
+                "false" | "no" | "n" | "off" | "0" => false,
+                _ => return Err(anyhow!("{value:?} is not a boolean")),
+            }),
+            FieldType::Duration { default_unit } => {
+                Value::Duration(parse_duration(trimmed, *default_unit)?)
+            }
+            FieldType::Ip => Value::Ip(
+                trimmed
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse()
+                    .with_context(|| format!("{value:?} is not an IP address"))?,
+            ),
+            FieldType::Enum { values } if values.is_empty() => {
+                Value::String(trimmed.to_uppercase())
+            }
+            FieldType::Enum { values } => Value::String(
+                values
+                    .iter()
+                    .find(|v| v.eq_ignore_ascii_case(trimmed))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{value:?} is not one of {values:?}"))?,
+            ),
+            FieldType::Json => Value::Json(
+                serde_json::from_str(trimmed)
+                    .with_context(|| format!("{value:?} is not valid JSON"))?,
+            ),
+        };
+        Ok(converted)
+    }
+}
+
+/// Log 中字段的值，序列化为对应的 JSON 类型
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// 输出为毫秒数
+    Duration(#[serde(serialize_with = "serialize_millis")] Duration),
+    Ip(IpAddr),
+    Json(serde_json::Value),
+}
+
+impl Value {
+    /// 字符串类型的值
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => f.write_str(s),
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Duration(d) => write!(f, "{}ms", d.as_secs_f64() * 1e3),
+            Value::Ip(ip) => write!(f, "{ip}"),
+            Value::Json(json) => write!(f, "{json}"),
+        }
+    }
+}
+
+fn serialize_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_f64(duration.as_secs_f64() * 1e3)
+}
+
+fn duration_unit(unit: &str) -> Option<Duration> {
+    let unit = match unit.to_ascii_lowercase().as_str() {
+        "ns" | "nanos" => Duration::from_nanos(1),
+        "us" | "µs" | "micros" => Duration::from_micros(1),
+        "ms" | "millis" => Duration::from_millis(1),
+        "s" | "sec" | "secs" | "seconds" => Duration::from_secs(1),
+        "m" | "min" | "mins" | "minutes" => Duration::from_secs(60),
+        "h" | "hour" | "hours" => Duration::from_secs(3600),
+        "d" | "day" | "days" => Duration::from_secs(86400),
+        _ => return None,
+    };
+    Some(unit)
+}
+
+/// 解析由若干个 `数字+单位` 组成的时长，只有一个数字时使用 `default_unit`
+fn parse_duration(value: &str, default_unit: Duration) -> anyhow::Result<Duration> {
+    if value.is_empty() {
+        return Err(anyhow!("empty duration"));
+    }
+    if let Ok(number) = value.parse::<f64>() {
+        return scale(number, default_unit, value);
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let unit_len = rest[number_len..]
+            .find(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+            .unwrap_or(rest.len() - number_len);
+        let number: f64 = rest[..number_len]
+            .parse()
+            .with_context(|| format!("{value:?} is not a duration"))?;
+        let unit = duration_unit(&rest[number_len..number_len + unit_len])
+            .ok_or_else(|| anyhow!("{value:?} is not a duration"))?;
+        total += scale(number, unit, value)?;
+        rest = rest[number_len + unit_len..].trim_start();
+    }
+    Ok(total)
+}
+
+fn scale(number: f64, unit: Duration, value: &str) -> anyhow::Result<Duration> {
+    Duration::try_from_secs_f64(number * unit.as_secs_f64())
+        .map_err(|_| anyhow!("{value:?} is not a valid duration"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_type(type_: i32, format_pattern: Option<&str>) -> anyhow::Result<FieldType> {
+        FieldType::from_field(&LogParserField {
+            id: 1,
+            log_parser_rule_id: 1,
+            name: None,
+            name_in_capture: "field".to_string(),
+            type_,
+            format_pattern: format_pattern.map(str::to_string),
+            default_val: None,
+            is_sensitive: None,
+            mask_strategy: None,
+        })
+    }
+
+    fn convert(type_: i32, format_pattern: Option<&str>, value: &str) -> anyhow::Result<Value> {
+        field_type(type_, format_pattern)?.convert(value)
+    }
+
+    fn json(value: Value) -> String {
+        serde_json::to_string(&value).unwrap()
+    }
+
+    #[test]
+    fn converts_scalars() {
+        assert_eq!(json(convert(0, None, " a ").unwrap()), r#"" a ""#);
+        assert_eq!(json(convert(1, None, " -42 ").unwrap()), "-42");
+        assert!(convert(1, None, "4.2").is_err());
+        assert_eq!(json(convert(2, None, "4.5").unwrap()), "4.5");
+        assert!(convert(2, None, "NaN").is_err());
+        assert!(convert(2, None, "inf").is_err());
+        for (value, expected) in [("YES", true), ("on", true), ("0", false), ("n", false)] {
+            assert_eq!(convert(3, None, value).unwrap(), Value::Boolean(expected));
+        }
+        assert!(convert(3, None, "maybe").is_err());
+        assert_eq!(json(convert(5, None, "10.0.0.1").unwrap()), r#""10.0.0.1""#);
+        assert_eq!(json(convert(5, None, "[::1]").unwrap()), r#""::1""#);
+        assert!(convert(5, None, "10.0.0.256").is_err());
+        assert_eq!(
+            json(convert(7, None, r#"{"a": [1]}"#).unwrap()),
+            r#"{"a":[1]}"#
+        );
+        assert!(convert(7, None, "{").is_err());
+        assert!(field_type(8, None).is_err());
+    }
+
+    #[test]
+    fn converts_durations_to_millis() {
+        assert_eq!(json(convert(4, None, "250").unwrap()), "250.0");
+        assert_eq!(json(convert(4, Some("s"), "1.5").unwrap()), "1500.0");
+        assert_eq!(json(convert(4, None, "1h30m").unwrap()), "5400000.0");
+        assert_eq!(json(convert(4, None, "2s 500ms").unwrap()), "2500.0");
+        assert_eq!(json(convert(4, None, "750us").unwrap()), "0.75");
+        assert!(convert(4, None, "").is_err());
+        assert!(convert(4, None, "5 parsecs").is_err());
+        assert!(convert(4, None, "-1").is_err());
+        assert!(field_type(4, Some("fortnight")).is_err());
+    }
+
+    #[test]
+    fn enums_match_configured_spelling() {
+        assert_eq!(
+            convert(6, Some("Info, Warn"), "WARN").unwrap().as_str(),
+            Some("Warn")
+        );
+        assert!(convert(6, Some("Info,Warn"), "debug").is_err());
+        assert_eq!(convert(6, None, "debug").unwrap().as_str(), Some("DEBUG"));
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const RULES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/rules/default.yaml");
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_log-resolver-rs"))
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .env("DATETIME_ZONE", "UTC")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_command_traces_each_event() {
+    let output = run(
+        &[
+            "test",
+            "--rules",
+            RULES,
+            "--subsys",
+            "SUBSYS_TEST",
+            "--header",
+            "filename=a.log",
+        ],
+        "2024-01-01 10:00:00.000 | INFO | hi\nbad\n",
+    );
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("event at line 0 (subsys_log_parser 1): log_parser_pattern 1"));
+    assert!(stdout.contains(r#"level = "INFO""#));
+    assert!(stdout.contains(r#""filename":"a.log""#));
+    assert!(stdout.contains("event at line 1 (subsys_log_parser 1): no pattern matched"));
+    assert!(stdout.contains("    | bad"));
+}
+
+#[test]
+fn test_command_fails_when_a_record_cannot_be_resolved() {
+    let output = run(
+        &["test", "--rules", RULES, "--per-line"],
+        "[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO | hi\n[[subsyscode=NOPE]]x\n",
+    );
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("record 2\n  error: unknown_subsys: unknown subsys NOPE"));
+
+    let output = run(&["test", "--rules", RULES, "--header", "a=b"], "");
+    assert!(!output.status.success(), "--header requires --subsys");
+}
+
+#[test]
+fn validate_command_fails_on_warnings_only_when_strict() {
+    let output = run(&["validate", "--rules", RULES], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("0 error(s), 1 warning(s)"));
+
+    let output = run(&["validate", "--rules", RULES, "--strict"], "");
+    assert!(!output.status.success());
+}
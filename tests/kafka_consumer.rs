@@ -0,0 +1,174 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use log_resolver_rs::configuration::ConsumerConfig;
+use log_resolver_rs::consumer::{KafkaRecordSource, PartitionOffsets, Record};
+use rdkafka::config::ClientConfig;
+use rdkafka::mocking::MockCluster;
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+
+const TOPIC: &str = "raw-logs";
+
+fn produce(bootstrap_servers: &str, values: &[&str]) {
+    let producer: BaseProducer = ClientConfig::new()
+        .set("bootstrap.servers", bootstrap_servers)
+        .create()
+        .unwrap();
+    for (i, value) in values.iter().enumerate() {
+        let key = format!("key-{i}");
+        producer
+            .send(BaseRecord::to(TOPIC).key(&key).payload(*value))
+            .unwrap();
+    }
+    producer.flush(Duration::from_secs(10)).unwrap();
+}
+
+fn consumer_config(bootstrap_servers: &str, group_id: &str) -> ConsumerConfig {
+    ConsumerConfig {
+        brokers: bootstrap_servers.to_string(),
+        group_id: group_id.to_string(),
+        topics: vec![TOPIC.to_string()],
+        poll_timeout: Duration::from_millis(200),
+        auto_offset_reset: "earliest".to_string(),
+        // mock broker 在成员离开后仍会等待 session 超时才完成 rebalance
+        properties: vec![
+            ("session.timeout.ms".to_string(), "6000".to_string()),
+            ("heartbeat.interval.ms".to_string(), "500".to_string()),
+        ],
+    }
+}
+
+/// 拉取直到收到 `count` 条记录或超时
+fn consume(config: &ConsumerConfig, count: usize, fail_on: Option<&str>) -> Vec<String> {
+    let source = KafkaRecordSource::new(config).unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let shutdown = shutdown.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(30));
+            shutdown.store(true, Ordering::Relaxed);
+        });
+    }
+    let mut values = Vec::new();
+    let mut handled = PartitionOffsets::default();
+    let result = source.run_with_idle(&shutdown, |record| {
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let value = String::from_utf8(record.value.clone()).unwrap();
+        if fail_on == Some(value.as_str()) {
+            anyhow::bail!("handler failed on {value}");
+        }
+        values.push(value);
+        if values.len() >= count {
+            shutdown.store(true, Ordering::Relaxed);
+        }
+        handled.handled(record);
+        Ok(Some(handled.clone()))
+    });
+    assert_eq!(result.is_err(), fail_on.is_some());
+    values
+}
+
+#[test]
+fn consumes_records_and_commits_only_handled_offsets() {
+    let cluster = MockCluster::new(1).unwrap();
+    cluster.create_topic(TOPIC, 1, 1).unwrap();
+    let servers = cluster.bootstrap_servers();
+    produce(&servers, &["a", "b", "c"]);
+
+    let config = consumer_config(&servers, "test-group");
+
+    // handler 在 "b" 上失败: "a" 被提交，"b" 不会
+    let values = consume(&config, 3, Some("b"));
+    assert_eq!(values, vec!["a"]);
+
+    // 同一个消费组重新消费时从 "b" 开始
+    let values = consume(&config, 2, None);
+    assert_eq!(values, vec!["b", "c"]);
+
+    produce(&servers, &["d"]);
+    let values = consume(&config, 1, None);
+    assert_eq!(values, vec!["d"]);
+}
+
+#[test]
+fn record_carries_key_and_position() {
+    let cluster = MockCluster::new(1).unwrap();
+    cluster.create_topic(TOPIC, 1, 1).unwrap();
+    let servers = cluster.bootstrap_servers();
+    produce(
+        &servers,
+        &["[[subsyscode=SUBSYS_TEST]]2025-01-01 22:22:22.222 |INFO| MSG"],
+    );
+
+    let source = KafkaRecordSource::new(&consumer_config(&servers, "position-group")).unwrap();
+    let record = (0..50)
+        .find_map(|_| source.poll().unwrap())
+        .expect("no record received");
+    assert_eq!(record.key, "key-0");
+    assert_eq!(record.topic, TOPIC);
+    assert_eq!(record.partition, 0);
+    assert_eq!(record.offset, 0);
+    assert!(record.timestamp > 0);
+    let mut handled = PartitionOffsets::default();
+    handled.handled(&record);
+    source.close(&handled).unwrap();
+}
+
+fn record(partition: i32, offset: i64) -> Record {
+    Record {
+        key: String::new(),
+        value: Vec::new(),
+        timestamp: 0,
+        topic: TOPIC.to_string(),
+        partition,
+        offset,
+    }
+}
+
+#[test]
+fn partition_offsets_keep_one_entry_per_partition() {
+    let mut offsets = PartitionOffsets::default();
+    assert!(offsets.is_empty());
+    for offset in [3, 4, 5, 2] {
+        offsets.handled(&record(0, offset));
+    }
+    offsets.handled(&record(1, 7));
+    assert_eq!(offsets.get(TOPIC, 0), Some(6));
+    assert_eq!(offsets.get(TOPIC, 1), Some(8));
+    assert_eq!(offsets.get(TOPIC, 2), None);
+    assert_eq!(offsets.get("other", 0), None);
+}
+
+#[test]
+fn commits_only_offsets_returned_by_handler() {
+    let cluster = MockCluster::new(1).unwrap();
+    cluster.create_topic(TOPIC, 1, 1).unwrap();
+    let servers = cluster.bootstrap_servers();
+    produce(&servers, &["a", "b", "c"]);
+    let config = consumer_config(&servers, "batch-group");
+
+    // 只在 "a" 之后返回 offset，"b" 和 "c" 处理过但没有提交
+    let source = KafkaRecordSource::new(&config).unwrap();
+    let shutdown = AtomicBool::new(false);
+    let mut handled = PartitionOffsets::default();
+    source
+        .run_with_idle(&shutdown, |record| {
+            let Some(record) = record else {
+                return Ok(None);
+            };
+            handled.handled(record);
+            if record.value == b"c" {
+                shutdown.store(true, Ordering::Relaxed);
+            }
+            Ok((record.value == b"a").then(|| handled.clone()))
+        })
+        .unwrap();
+    drop(source);
+
+    let values = consume(&config, 2, None);
+    assert_eq!(values, vec!["b", "c"]);
+}
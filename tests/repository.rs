@@ -0,0 +1,131 @@
+use log_resolver_rs::repository::{
+    MemoryRuleRepository, RuleRepository, RuleRows, SqliteRuleRepository,
+};
+
+/// 乱序的 id，带一条未启用的 subsys_log_parser
+const RULES: &str = r#"
+sys_subsys_config:
+- {id: 2, sys_code: SYS, subsys_code: B}
+- {id: 1, sys_code: SYS, subsys_code: A, subsys_name: a}
+subsys_log_parser:
+- {id: 3, subsys_code: A, log_parser_rule_id: 1, status: true, source_topic: t3, file_name: 'a\.log'}
+- {id: 1, subsys_code: A, log_parser_rule_id: 1, status: true, log_split: "\n", source_topic: t1}
+- {id: 2, subsys_code: A, log_parser_rule_id: 1, status: false, source_topic: t2}
+- {id: 4, subsys_code: B, log_parser_rule_id: 2, status: true, source_topic: t4, use_header_pattern: true}
+log_parser_rule:
+- {id: 2, status: true}
+- {id: 1, name: default, status: true}
+log_parser_pattern:
+- {id: 2, log_parser_rule_id: 1, pattern: 'b'}
+- {id: 1, log_parser_rule_id: 1, pattern: 'a'}
+- {id: 3, log_parser_rule_id: 2, pattern: 'c'}
+log_parser_field:
+- {id: 1, log_parser_rule_id: 1, name_in_capture: level, type: 0}
+- {id: 2, log_parser_rule_id: 1, name_in_capture: card, type: 0, is_sensitive: true, mask_strategy: hmac}
+"#;
+
+fn rows() -> RuleRows {
+    serde_yaml::from_str(RULES).unwrap()
+}
+
+fn sqlite() -> SqliteRuleRepository {
+    let mut repository = SqliteRuleRepository::establish(":memory:").unwrap();
+    repository.replace_all(&rows()).unwrap();
+    repository
+}
+
+/// 两种存储的查询语义相同
+fn check_queries(repository: &mut dyn RuleRepository) {
+    let configs = repository.sys_subsys_configs().unwrap();
+    assert_eq!(configs.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1, 2]);
+    let a = repository
+        .sys_subsys_config_by_subsys_code("A")
+        .unwrap()
+        .unwrap();
+    assert_eq!(a.subsys_name.as_deref(), Some("a"));
+    assert!(
+        repository
+            .sys_subsys_config_by_subsys_code("C")
+            .unwrap()
+            .is_none()
+    );
+
+    let parsers = repository.subsys_log_parsers_by_subsys_code("A").unwrap();
+    assert_eq!(parsers.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(parsers[0].log_split.as_deref(), Some("\n"));
+    assert_eq!(parsers[1].file_name.as_deref(), Some(r"a\.log"));
+    let parsers = repository.subsys_log_parsers().unwrap();
+    assert_eq!(
+        parsers.iter().map(|r| r.id).collect::<Vec<_>>(),
+        vec![1, 3, 4]
+    );
+    assert!(parsers[2].use_header_pattern);
+
+    assert_eq!(
+        repository
+            .log_parser_rule_by_id(1)
+            .unwrap()
+            .unwrap()
+            .name
+            .as_deref(),
+        Some("default")
+    );
+    assert!(repository.log_parser_rule_by_id(9).unwrap().is_none());
+    assert_eq!(
+        repository
+            .log_parser_patterns_by_rule_id(1)
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    let card = repository
+        .log_parser_field_by_rule_id_and_name_in_capture(1, "card")
+        .unwrap()
+        .unwrap();
+    assert_eq!(card.is_sensitive, Some(true));
+    assert_eq!(card.mask_strategy.as_deref(), Some("hmac"));
+    assert!(
+        repository
+            .log_parser_field_by_rule_id_and_name_in_capture(2, "card")
+            .unwrap()
+            .is_none()
+    );
+
+    let all = repository.load_all().unwrap();
+    assert_eq!(all.subsys_log_parser.len(), 3);
+    assert_eq!(all.log_parser_pattern.len(), 3);
+    // 导出时包括未启用的配置
+    assert_eq!(repository.dump().unwrap().subsys_log_parser.len(), 4);
+}
+
+#[test]
+fn memory_repository_queries() {
+    check_queries(&mut MemoryRuleRepository::new(rows()));
+}
+
+#[test]
+fn sqlite_repository_queries() {
+    check_queries(&mut sqlite());
+}
+
+#[test]
+fn replace_all_keeps_ids_and_replaces_everything() {
+    let mut repository = sqlite();
+    let mut rows = rows();
+    rows.subsys_log_parser.retain(|r| r.id == 4);
+    rows.log_parser_field.clear();
+    repository.replace_all(&rows).unwrap();
+    let dumped = repository.dump().unwrap();
+    assert_eq!(
+        dumped
+            .subsys_log_parser
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>(),
+        vec![4]
+    );
+    assert!(dumped.log_parser_field.is_empty());
+    assert_eq!(dumped.sys_subsys_config.len(), 2);
+}
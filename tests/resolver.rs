@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use log_resolver_rs::error::{ErrorKind, ParseError};
+use log_resolver_rs::repository::{MemoryRuleRepository, RuleRepository, RuleRows};
+use log_resolver_rs::resolver::{Resolution, Resolver, RuleSource};
+use log_resolver_rs::rule_cache::{RuleCache, RuleSet};
+
+/// 子系统 SUBSYS_TEST，按行切分，事件格式为 `时间 | 级别 |内容`
+const RULES: &str = r#"
+sys_subsys_config:
+- id: 1
+  sys_code: SYS_TEST
+  subsys_code: SUBSYS_TEST
+subsys_log_parser:
+- id: 1
+  subsys_code: SUBSYS_TEST
+  log_parser_rule_id: 1
+  status: true
+  log_split: "\n"
+  source_topic: TOPIC
+log_parser_rule:
+- id: 1
+  status: true
+log_parser_pattern:
+- id: 1
+  log_parser_rule_id: 1
+  pattern: '^(?s)(?P<dateTime>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}\.\d{3})\s*\|\s*(?P<level>INFO|ERROR)\s*\|(?P<message>.*)$'
+log_parser_field:
+- id: 1
+  log_parser_rule_id: 1
+  name_in_capture: dateTime
+  type: 10
+  format_pattern: yyyy-MM-dd HH:mm:ss.SSS
+- id: 2
+  log_parser_rule_id: 1
+  name_in_capture: level
+  type: 0
+"#;
+
+fn rows() -> RuleRows {
+    serde_yaml::from_str(RULES).unwrap()
+}
+
+fn resolver(rows: RuleRows) -> Resolver {
+    Resolver::from_rule_set(RuleSet::from_rows(rows))
+}
+
+fn messages(resolution: &Resolution) -> Vec<String> {
+    resolution
+        .logs
+        .iter()
+        .map(|log| log.log_content.to_string())
+        .collect()
+}
+
+#[test]
+fn header_pattern_is_used_only_when_enabled() {
+    let raw = b"[[subsyscode=SUBSYS_TEST][pattern=\\n(?=\\d{4}-)]]\
+2024-01-01 10:00:00.000 | ERROR |boom\n  at a\n2024-01-01 10:00:01.000 | INFO |ok";
+
+    // 默认按 log_split 切分，堆栈行成为没有匹配的事件
+    let resolution = resolver(rows()).resolve(raw).unwrap();
+    assert_eq!(resolution.logs.len(), 2);
+    assert_eq!(resolution.unmatched.len(), 1);
+
+    let mut rows = rows();
+    rows.subsys_log_parser[0].use_header_pattern = true;
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    assert_eq!(
+        messages(&resolution),
+        vec![
+            "2024-01-01 10:00:00.000 | ERROR |boom\n  at a",
+            "2024-01-01 10:00:01.000 | INFO |ok"
+        ]
+    );
+    assert!(resolution.unmatched.is_empty());
+    assert_eq!(resolution.logs[1].line_offset, 2);
+}
+
+#[test]
+fn invalid_header_pattern_falls_back_to_log_split() {
+    let mut rows = rows();
+    rows.subsys_log_parser[0].use_header_pattern = true;
+    let raw = b"[[subsyscode=SUBSYS_TEST][pattern=(]]\
+2024-01-01 10:00:00.000 | INFO |a\n2024-01-01 10:00:01.000 | INFO |b";
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    assert_eq!(resolution.logs.len(), 2);
+}
+
+#[test]
+fn sensitive_fields_are_masked_in_attr_and_content() {
+    let mut rows = rows();
+    rows.log_parser_field.push(
+        serde_yaml::from_str(
+            "{id: 3, log_parser_rule_id: 1, name_in_capture: message, type: 0, \
+             is_sensitive: true, mask_strategy: 'partial:2'}",
+        )
+        .unwrap(),
+    );
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |secret";
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    let log = &resolution.logs[0];
+    assert_eq!(log.log_header.attr["message"].as_str(), Some("****et"));
+    assert_eq!(log.log_content, "2024-01-01 10:00:00.000 | INFO |****et");
+}
+
+#[test]
+fn sensitive_content_is_masked_for_every_parser() {
+    let mut rows = rows();
+    rows.log_parser_field.push(
+        serde_yaml::from_str(
+            "{id: 3, log_parser_rule_id: 1, name_in_capture: message, type: 0, \
+             is_sensitive: true}",
+        )
+        .unwrap(),
+    );
+    // 第二个 parser 整行匹配，没有敏感字段
+    rows.subsys_log_parser.push(
+        serde_yaml::from_str(
+            "{id: 2, subsys_code: SUBSYS_TEST, log_parser_rule_id: 2, status: true, \
+             log_split: \"\\n\", source_topic: OTHER}",
+        )
+        .unwrap(),
+    );
+    rows.log_parser_rule
+        .push(serde_yaml::from_str("{id: 2, status: true}").unwrap());
+    rows.log_parser_pattern.push(
+        serde_yaml::from_str("{id: 2, log_parser_rule_id: 2, pattern: '^(?P<line>.*)$'}").unwrap(),
+    );
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |secret";
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    assert_eq!(resolution.logs.len(), 2);
+    for log in &resolution.logs {
+        assert_eq!(log.log_content, "2024-01-01 10:00:00.000 | INFO |******");
+    }
+}
+
+#[test]
+fn conversion_errors_do_not_leak_sensitive_values() {
+    let mut rows = rows();
+    rows.log_parser_field[0].is_sensitive = Some(true);
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-13-45 99:00:00.000 | INFO |msg";
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    let log = &resolution.logs[0];
+    assert_eq!(log.errors.len(), 1);
+    let message = serde_json::to_string(&log.errors).unwrap();
+    assert!(!message.contains("2024-13-45"), "{message}");
+    assert!(!log.log_content.contains("2024-13-45"));
+}
+
+#[test]
+fn missing_or_empty_captures_use_default_val() {
+    let mut rows = rows();
+    rows.log_parser_pattern[0].pattern = Some(
+        r"^(?P<dateTime>\S+ \S+) \| (?P<level>\w+) \|(?:\[(?P<traceId>[^\]]*)\])?(?P<message>.*)$"
+            .to_string(),
+    );
+    rows.log_parser_field.push(
+        serde_yaml::from_str(
+            "{id: 3, log_parser_rule_id: 1, name_in_capture: traceId, type: 0, default_val: '-'}",
+        )
+        .unwrap(),
+    );
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |[abc]a\n\
+2024-01-01 10:00:00.000 | INFO |[]b\n2024-01-01 10:00:00.000 | INFO |c";
+    let resolution = resolver(rows).resolve(raw).unwrap();
+    let trace_ids: Vec<_> = resolution
+        .logs
+        .iter()
+        .map(|log| log.log_header.attr["traceId"].to_string())
+        .collect();
+    assert_eq!(trace_ids, vec!["abc", "-", "-"]);
+    // 没有 log_parser_field 的分组没有参与匹配时不输出
+    assert_eq!(
+        resolution.logs[2].log_header.attr["message"].as_str(),
+        Some("c")
+    );
+}
+
+#[test]
+fn resolves_events_into_logs() {
+    let raw = b"[[subsyscode=SUBSYS_TEST][file_line=10]]\
+2024-01-01 10:00:00.000 | INFO |a\n2024-01-01 10:00:01.000 | ERROR |b\nnot a log";
+    let resolution = resolver(rows()).resolve(raw).unwrap();
+    assert_eq!(resolution.subsys_code, "SUBSYS_TEST");
+    assert_eq!(resolution.log_parser_rule_ids, vec![1]);
+    assert_eq!(resolution.logs.len(), 2);
+    let log = &resolution.logs[1];
+    assert_eq!(log.source_topic, "TOPIC");
+    assert_eq!(log.line_offset, 1);
+    assert_eq!(log.file_line, Some(11));
+    assert_eq!(log.log_header.attr["level"].as_str(), Some("ERROR"));
+    assert!(matches!(
+        resolution.unmatched[..],
+        [ParseError::NoMatchingPattern {
+            subsys_log_parser_id: 1,
+            line_offset: 2
+        }]
+    ));
+}
+
+#[test]
+fn whole_record_errors() {
+    let resolver = resolver(rows());
+    assert!(matches!(
+        resolver.resolve(b"[[subsyscode=OTHER]]x"),
+        Err(ParseError::UnknownSubsys(code)) if code == "OTHER"
+    ));
+    assert!(matches!(
+        resolver.resolve(b"[[encode=UTF-8]]x"),
+        Err(ParseError::MissingSubsys)
+    ));
+    assert!(matches!(
+        resolver.resolve(b"no header"),
+        Err(ParseError::MissingDelimiter)
+    ));
+}
+
+#[test]
+fn trace_reports_captures_of_each_event() {
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |a\nnot a log";
+    let (resolution, traces) = resolver(rows()).trace(raw).unwrap();
+    assert_eq!(resolution.logs.len(), 1);
+    assert_eq!(traces.len(), 2);
+    assert_eq!(traces[0].pattern_id, Some(1));
+    assert!(
+        traces[0]
+            .captures
+            .contains(&("message".to_string(), Some("a".to_string())))
+    );
+    assert_eq!(traces[1].pattern_id, None);
+    assert_eq!(traces[1].content, "not a log");
+}
+
+#[test]
+fn resolver_on_a_rule_cache_sees_refreshed_rules() {
+    let mut repository = MemoryRuleRepository::new(rows());
+    let cache = Arc::new(RuleCache::load(&mut repository, false).unwrap());
+    let resolver = Resolver::new(cache.clone());
+    let raw = b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |a";
+    assert_eq!(resolver.resolve(raw).unwrap().logs[0].source_topic, "TOPIC");
+
+    let mut rows = rows();
+    rows.subsys_log_parser[0].source_topic = "NEW_TOPIC".to_string();
+    repository.replace_all(&rows).unwrap();
+    assert!(cache.refresh(&mut repository).unwrap());
+    assert_eq!(
+        resolver.resolve(raw).unwrap().logs[0].source_topic,
+        "NEW_TOPIC"
+    );
+}
+
+/// 规则存储一直无法访问
+struct UnavailableRules;
+
+impl RuleSource for UnavailableRules {
+    fn rules(&self) -> Result<Arc<RuleSet>, ParseError> {
+        Err(ParseError::repository("connection refused"))
+    }
+}
+
+#[test]
+fn unavailable_repository_is_its_own_error_class() {
+    let resolver = Resolver::new(Arc::new(UnavailableRules));
+    let error = resolver
+        .resolve(b"[[subsyscode=SUBSYS_TEST]]2024-01-01 10:00:00.000 | INFO |a")
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::RepositoryUnavailable);
+    assert!(error.to_string().contains("connection refused"));
+
+    let error = anyhow::Error::new(error).context("while resolving");
+    assert!(ParseError::is_repository_unavailable(&error));
+    assert!(!ParseError::is_repository_unavailable(&anyhow::anyhow!(
+        "invalid rules"
+    )));
+}